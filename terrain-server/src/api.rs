@@ -0,0 +1,43 @@
+use axum::{
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+/// Error returned by the JSON API handlers, rendered as a plain-text body.
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    BadRequest(String),
+    /// Missing or wrong credentials.
+    Unauthorized(String),
+    /// The operation is turned off on this server.
+    Forbidden(String),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn map_not_found(name: &str) -> Self {
+        ApiError::NotFound(format!("Map {} not found", name))
+    }
+
+    pub fn chunk_not_found(map: &str, chunk: &str) -> Self {
+        ApiError::NotFound(format!("Chunk {} not found in {}", chunk, map))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, msg).into_response(),
+            ApiError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+            ApiError::Unauthorized(msg) => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                msg,
+            )
+                .into_response(),
+            ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg).into_response(),
+            ApiError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response(),
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Serves map files from memory with content negotiation and caching.
+//!
+//! Every map can be sent as its original JSON or in the binary encoding from
+//! `binary.rs`, each either uncompressed or compressed with gzip, brotli or
+//! zstd. Variants are built the first time they are requested and then kept
+//! for the lifetime of the `LoadedMap`, so a re-exported map starts over with
+//! a fresh cache. Responses carry a per-variant `ETag` and the file's
+//! `Last-Modified`, and conditional requests are answered with `304`.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::{
+    io::Write,
+    sync::{Arc, OnceLock},
+    time::SystemTime,
+};
+
+use crate::{AppState, binary, map::LoadedMap};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+impl Format {
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Binary => binary::CONTENT_TYPE,
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Binary => "bin",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Format::Json => 0,
+            Format::Binary => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    /// Ties in the client's preferences are broken in this order.
+    const PREFERENCE: [Encoding; 4] = [
+        Encoding::Brotli,
+        Encoding::Zstd,
+        Encoding::Gzip,
+        Encoding::Identity,
+    ];
+
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Encoding::Identity => 0,
+            Encoding::Gzip => 1,
+            Encoding::Brotli => 2,
+            Encoding::Zstd => 3,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Bytes {
+        let compressed = match self {
+            Encoding::Identity => return Bytes::copy_from_slice(data),
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data).and_then(|_| encoder.finish())
+            }
+            Encoding::Brotli => {
+                let mut out = Vec::new();
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 9,
+                    lgwin: 22,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &data[..], &mut out, &params).map(|_| out)
+            }
+            Encoding::Zstd => zstd::encode_all(data, 19),
+        };
+        // Compressing into memory cannot fail
+        Bytes::from(compressed.expect("in-memory compression failed"))
+    }
+
+    /// Picks the best encoding allowed by an `Accept-Encoding` header.
+    fn negotiate(accept: Option<&str>) -> Encoding {
+        let Some(accept) = accept else {
+            return Encoding::Identity;
+        };
+
+        let mut weights = [None::<f32>; 4];
+        let mut wildcard = None;
+        for item in accept.split(',') {
+            let mut parts = item.split(';');
+            let token = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if token == "*" {
+                wildcard = Some(q);
+            } else if let Some(enc) = Encoding::PREFERENCE.iter().find(|e| e.token() == token) {
+                weights[enc.index()] = Some(q);
+            }
+        }
+
+        let weight = |enc: Encoding| match (weights[enc.index()], enc) {
+            (Some(q), _) => q,
+            // identity is acceptable unless explicitly refused
+            (None, Encoding::Identity) => wildcard.unwrap_or(1.0).max(f32::MIN_POSITIVE),
+            (None, _) => wildcard.unwrap_or(0.0),
+        };
+
+        let mut best = Encoding::Identity;
+        let mut best_q = 0.0;
+        for enc in Encoding::PREFERENCE {
+            let q = weight(enc);
+            if q > best_q {
+                best = enc;
+                best_q = q;
+            }
+        }
+        best
+    }
+}
+
+/// Lazily built representations of one map file.
+pub struct MapAssets {
+    json: Bytes,
+    modified: SystemTime,
+    hash: u64,
+    binary: OnceLock<Bytes>,
+    variants: [[OnceLock<Bytes>; 4]; 2],
+}
+
+impl MapAssets {
+    pub fn new(json: Bytes, modified: SystemTime) -> Self {
+        MapAssets {
+            hash: fnv1a(&json),
+            json,
+            modified,
+            binary: OnceLock::new(),
+            variants: Default::default(),
+        }
+    }
+
+    fn etag(&self, format: Format, encoding: Encoding) -> String {
+        format!(
+            "\"{:016x}-{}-{}\"",
+            self.hash,
+            format.tag(),
+            encoding.token()
+        )
+    }
+
+    /// Hash of the file contents, for keying things derived from it.
+    pub fn content_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Size of the map file in bytes.
+    pub fn file_size(&self) -> usize {
+        self.json.len()
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    fn last_modified(&self) -> String {
+        httpdate::fmt_http_date(self.modified)
+    }
+}
+
+impl LoadedMap {
+    fn uncompressed(&self, format: Format) -> &Bytes {
+        match format {
+            Format::Json => &self.assets.json,
+            Format::Binary => self
+                .assets
+                .binary
+                .get_or_init(|| Bytes::from(binary::encode(&self.data))),
+        }
+    }
+
+    /// Returns the requested representation, compressing it on first use.
+    pub fn variant(&self, format: Format, encoding: Encoding) -> Bytes {
+        if encoding == Encoding::Identity {
+            return self.uncompressed(format).clone();
+        }
+        self.assets.variants[format.index()][encoding.index()]
+            .get_or_init(|| encoding.compress(self.uncompressed(format)))
+            .clone()
+    }
+}
+
+/// 64-bit FNV-1a, used to derive stable ETags from the file contents.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Deserialize)]
+pub struct FormatQuery {
+    format: Option<String>,
+}
+
+fn requested_format(query: &FormatQuery, headers: &HeaderMap) -> Format {
+    if let Some(format) = &query.format {
+        return if format == "bin" {
+            Format::Binary
+        } else {
+            Format::Json
+        };
+    }
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return Format::Json;
+    };
+    let (binary, named) = media_weight(accept, binary::CONTENT_TYPE);
+    let (json, _) = media_weight(accept, Format::Json.content_type());
+    // Wildcards alone keep the default; naming the binary type wins ties
+    if binary > json || (named && binary > 0.0 && binary == json) {
+        Format::Binary
+    } else {
+        Format::Json
+    }
+}
+
+/// Quality an `Accept` header gives `media_type`, taken from the most
+/// specific range that matches it, and whether that range names it exactly.
+fn media_weight(accept: &str, media_type: &str) -> (f32, bool) {
+    let major = media_type.split('/').next().unwrap_or("");
+    let mut best = None::<(u8, f32)>;
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let range = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let specificity = match range.split_once('/') {
+            _ if range == media_type => 2,
+            Some((m, "*")) if m == major => 1,
+            Some(("*", "*")) => 0,
+            _ => continue,
+        };
+        let q = parts
+            .filter_map(|p| {
+                let (key, value) = p.split_once('=')?;
+                key.trim().eq_ignore_ascii_case("q").then(|| value.trim())
+            })
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if best.is_none_or(|(s, _)| specificity > s) {
+            best = Some((specificity, q));
+        }
+    }
+    best.map_or((0.0, false), |(s, q)| (q, s == 2))
+}
+
+/// `true` if the client's cached copy is still current.
+pub fn not_modified(headers: &HeaderMap, etag: &str, modified: SystemTime) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+        return if_none_match.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+    let Some(since) = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+    else {
+        return false;
+    };
+    // HTTP dates have one-second resolution
+    let secs = |t: SystemTime| {
+        t.duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    };
+    secs(since) >= secs(modified)
+}
+
+pub async fn serve_map(
+    State(state): State<Arc<AppState>>,
+    Path(file): Path<String>,
+    Query(query): Query<FormatQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let map = match state.map(&file).await {
+        Ok(map) => map,
+        Err(e) => return e.into_response(),
+    };
+
+    let format = requested_format(&query, &headers);
+    let encoding = Encoding::negotiate(
+        headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok()),
+    );
+    let etag = map.assets.etag(format, encoding);
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::VARY,
+        HeaderValue::from_static("accept, accept-encoding"),
+    );
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&map.assets.last_modified()) {
+        response_headers.insert(header::LAST_MODIFIED, value);
+    }
+
+    if not_modified(&headers, &etag, map.assets.modified) {
+        return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+    }
+
+    // Compression at high levels takes a while on the larger maps
+    let body = match tokio::task::spawn_blocking(move || map.variant(format, encoding)).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    if encoding != Encoding::Identity {
+        response_headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.token()),
+        );
+    }
+    (response_headers, body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(accept: &str) -> Format {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+        requested_format(&FormatQuery { format: None }, &headers)
+    }
+
+    #[test]
+    fn binary_is_only_sent_when_asked_for() {
+        assert_eq!(format("application/octet-stream"), Format::Binary);
+        assert_eq!(
+            format("application/octet-stream, */*;q=0.1"),
+            Format::Binary
+        );
+        assert_eq!(format("Application/Octet-Stream; Q=0.5"), Format::Binary);
+        assert_eq!(format("*/*"), Format::Json);
+        assert_eq!(format("application/*"), Format::Json);
+        assert_eq!(format("text/html"), Format::Json);
+    }
+
+    #[test]
+    fn accept_quality_values_are_respected() {
+        assert_eq!(format("application/octet-stream;q=0"), Format::Json);
+        assert_eq!(format("application/octet-stream;q=0, */*"), Format::Json);
+        assert_eq!(
+            format("application/json;q=0.9, application/octet-stream;q=0.5"),
+            Format::Json
+        );
+        assert_eq!(
+            format("application/json;q=0.5, application/octet-stream;q=0.9"),
+            Format::Binary
+        );
+        assert_eq!(
+            format("application/json;q=0, application/*"),
+            Format::Binary
+        );
+    }
+
+    #[test]
+    fn accept_encoding_prefers_brotli_and_honours_refusals() {
+        assert_eq!(Encoding::negotiate(None), Encoding::Identity);
+        assert_eq!(
+            Encoding::negotiate(Some("gzip, br, zstd")),
+            Encoding::Brotli
+        );
+        assert_eq!(Encoding::negotiate(Some("gzip, br;q=0")), Encoding::Gzip);
+        assert_eq!(
+            Encoding::negotiate(Some("*;q=0, identity")),
+            Encoding::Identity
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Compact binary encoding of a map, served from `/maps/{file}` when the
+//! client asks for `application/octet-stream` (or passes `?format=bin`).
+//!
+//! All values are little-endian:
+//!
+//! ```text
+//! magic        [u8; 4]  "U2TM"
+//! version      u16      1
+//! scene        u16 length + UTF-8 bytes
+//! chunk count  u32
+//! per chunk:
+//!   name       u16 length + UTF-8 bytes
+//!   x y z width depth maxHeight   f32 × 6
+//!   resolution u32
+//!   encoding   u8       0 = i16 quantized, 1 = raw f32
+//!   heights    resolution² × (i16 | f32)
+//! ```
+//!
+//! Quantized heights are Unity's raw 16-bit samples, which are signed (some
+//! maps dip below the terrain origin), and decode exactly like the exporter
+//! does: `(q as f32 / 32768.0) * maxHeight`. A chunk is only quantized if every
+//! sample survives that round trip bit for bit; otherwise it falls back to raw
+//! `f32`, so the encoding is always lossless.
+
+use crate::map::{MapFile, TerrainChunk};
+
+pub const MAGIC: &[u8; 4] = b"U2TM";
+pub const VERSION: u16 = 1;
+pub const CONTENT_TYPE: &str = "application/octet-stream";
+
+const ENCODING_I16: u8 = 0;
+const ENCODING_F32: u8 = 1;
+
+/// Same normalization the exporter uses for Unity's `m_Heights`.
+const HEIGHT_SCALE: f32 = 32768.0;
+
+fn dequantize(q: i16, max_height: f32) -> f32 {
+    (q as f32 / HEIGHT_SCALE) * max_height
+}
+
+/// Recovers the raw Unity samples of a chunk, or `None` if any height is not
+/// exactly reproducible from an `i16`.
+fn quantize(chunk: &TerrainChunk) -> Option<Vec<i16>> {
+    chunk
+        .height_map
+        .iter()
+        .map(|&h| {
+            let q = (h / chunk.max_height * HEIGHT_SCALE).round();
+            if !(i16::MIN as f32..=i16::MAX as f32).contains(&q) {
+                return None;
+            }
+            let q = q as i16;
+            (dequantize(q, chunk.max_height).to_bits() == h.to_bits()).then_some(q)
+        })
+        .collect()
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    // Scene and chunk names are short identifiers; truncate rather than fail,
+    // keeping whole characters
+    let end = s
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|&end| end <= u16::MAX as usize)
+        .last()
+        .unwrap_or(0);
+    let bytes = &s.as_bytes()[..end];
+    out.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+pub fn encode(map: &MapFile) -> Vec<u8> {
+    let samples: usize = map.terrains.iter().map(|c| c.height_map.len()).sum();
+    let mut out = Vec::with_capacity(64 + map.terrains.len() * 64 + samples * 2);
+
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    put_str(&mut out, &map.scene);
+    out.extend_from_slice(&(map.terrains.len() as u32).to_le_bytes());
+
+    for chunk in &map.terrains {
+        put_str(&mut out, &chunk.name);
+        for value in [
+            chunk.x,
+            chunk.y,
+            chunk.z,
+            chunk.width,
+            chunk.depth,
+            chunk.max_height,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&chunk.resolution.to_le_bytes());
+
+        match quantize(chunk) {
+            Some(quantized) => {
+                out.push(ENCODING_I16);
+                for q in quantized {
+                    out.extend_from_slice(&q.to_le_bytes());
+                }
+            }
+            None => {
+                out.push(ENCODING_F32);
+                for h in &chunk.height_map {
+                    out.extend_from_slice(&h.to_le_bytes());
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        offset: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, n: usize) -> &'a [u8] {
+            let slice = &self.bytes[self.offset..self.offset + n];
+            self.offset += n;
+            slice
+        }
+
+        fn u16(&mut self) -> u16 {
+            u16::from_le_bytes(self.take(2).try_into().unwrap())
+        }
+
+        fn u32(&mut self) -> u32 {
+            u32::from_le_bytes(self.take(4).try_into().unwrap())
+        }
+
+        fn f32(&mut self) -> f32 {
+            f32::from_le_bytes(self.take(4).try_into().unwrap())
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.u16() as usize;
+            String::from_utf8(self.take(len).to_vec()).unwrap()
+        }
+    }
+
+    /// Reads the format back the way `decodeBinaryMap` in the viewer's
+    /// main.js does, along with each chunk's encoding byte.
+    fn decode(bytes: &[u8]) -> (MapFile, Vec<u8>) {
+        let mut reader = Reader { bytes, offset: 0 };
+        assert_eq!(reader.take(4), MAGIC);
+        assert_eq!(reader.u16(), VERSION);
+        let scene = reader.string();
+        let count = reader.u32();
+
+        let mut terrains = Vec::new();
+        let mut encodings = Vec::new();
+        for _ in 0..count {
+            let name = reader.string();
+            let [x, y, z, width, depth, max_height] = [(); 6].map(|_| reader.f32());
+            let resolution = reader.u32();
+            let encoding = reader.take(1)[0];
+            let height_map = (0..resolution * resolution)
+                .map(|_| {
+                    if encoding == ENCODING_I16 {
+                        // Math.fround(Math.fround(q / 32768) * maxHeight)
+                        let q = i16::from_le_bytes(reader.take(2).try_into().unwrap());
+                        (q as f32 / 32768.0) * max_height
+                    } else {
+                        reader.f32()
+                    }
+                })
+                .collect();
+            encodings.push(encoding);
+            terrains.push(TerrainChunk {
+                name,
+                x,
+                y,
+                z,
+                width,
+                depth,
+                max_height,
+                resolution,
+                height_map,
+            });
+        }
+        assert_eq!(reader.offset, bytes.len());
+        (MapFile { scene, terrains }, encodings)
+    }
+
+    fn assert_same_bits(a: &MapFile, b: &MapFile) {
+        assert_eq!(a.scene, b.scene);
+        assert_eq!(a.terrains.len(), b.terrains.len());
+        for (a, b) in a.terrains.iter().zip(&b.terrains) {
+            assert_eq!(a.name, b.name);
+            for (a, b) in [
+                (a.x, b.x),
+                (a.y, b.y),
+                (a.z, b.z),
+                (a.width, b.width),
+                (a.depth, b.depth),
+                (a.max_height, b.max_height),
+            ] {
+                assert_eq!(a.to_bits(), b.to_bits());
+            }
+            assert_eq!(a.resolution, b.resolution);
+            let bits =
+                |c: &TerrainChunk| c.height_map.iter().map(|h| h.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(a), bits(b), "heights of {}", a.name);
+        }
+    }
+
+    /// Heights as the exporter writes them: `(float)val / 32768.0f * scaleY`.
+    fn exported(samples: &[i16], max_height: f32) -> Vec<f32> {
+        samples
+            .iter()
+            .map(|&q| (q as f32 / 32768.0) * max_height)
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_exporter_values_bit_for_bit() {
+        // As found in ps0_10_cave4.json, after a trip through JSON text
+        let json = br#"{
+            "scene": "ps0_10_cave4",
+            "terrains": [{
+                "name": "Terrain_2355", "x": -64.0, "y": 0.0, "z": -64.0,
+                "width": 16.0, "depth": 16.0, "maxHeight": 16.0, "resolution": 2,
+                "heightMap": [4.9995117, 7.6796875, 4.9995117, 0.0]
+            }]
+        }"#;
+        let mut map = MapFile::from_slice(json).unwrap();
+        // Negative samples and both ends of Unity's range
+        map.terrains.push(TerrainChunk {
+            name: "Terrain_extremes".to_string(),
+            x: 0.0,
+            y: -12.5,
+            z: 0.0,
+            width: 600.0,
+            depth: 600.0,
+            max_height: 613.7,
+            resolution: 3,
+            height_map: exported(
+                &[i16::MIN, -1, 0, 1, 12345, 16384, 32766, i16::MAX, -7],
+                613.7,
+            ),
+        });
+        // Some raw samples that don't fit an i16 force a fallback
+        map.terrains.push(TerrainChunk {
+            name: "Terrain_resampled".to_string(),
+            x: 600.0,
+            y: 0.0,
+            z: 0.0,
+            width: 16.0,
+            depth: 16.0,
+            max_height: 16.0,
+            resolution: 2,
+            height_map: vec![0.1, 1.0 / 3.0, 4.9995117, -2.75],
+        });
+
+        let (decoded, encodings) = decode(&encode(&map));
+        assert_eq!(encodings, [ENCODING_I16, ENCODING_I16, ENCODING_F32]);
+        assert_same_bits(&map, &decoded);
+    }
+
+    #[test]
+    fn truncates_long_names_between_characters() {
+        let map = MapFile {
+            scene: "é".repeat(40_000),
+            terrains: Vec::new(),
+        };
+        let (decoded, _) = decode(&encode(&map));
+        assert_eq!(decoded.scene, "é".repeat(32_767));
+    }
+}
@@ -0,0 +1,266 @@
+//! Server settings, merged from (lowest to highest priority) built-in
+//! defaults, an optional TOML file, command-line flags and `TERRAIN_SERVER_*`
+//! environment variables.
+
+use axum::http::HeaderValue;
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+
+use crate::import::ImportOptions;
+
+/// Looked for in the working directory, then next to `Cargo.toml`, when no
+/// `--config` is given.
+const DEFAULT_CONFIG_FILE: &str = "terrain-server.toml";
+const ENV_PREFIX: &str = "TERRAIN_SERVER_";
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Serves Unity terrain maps to the Three.js viewer")]
+pub struct Cli {
+    /// TOML config file (default: terrain-server.toml in the working
+    /// directory or the crate root, if it exists)
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub overrides: Overrides,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Without a subcommand the server runs.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check exported maps against the format; exits non-zero on problems
+    Validate {
+        /// Directory of map JSON files, or a single map file
+        path: PathBuf,
+
+        /// Rewrite files whose problems can be repaired
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Build a map from a RAW/R16 or grayscale PNG heightmap
+    Import {
+        /// Heightmap file
+        input: PathBuf,
+
+        /// Map file to write (default: the input with a .json extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+}
+
+/// Settings that can come from the config file, the command line or the
+/// environment. Unset fields fall through to the next source.
+#[derive(clap::Args, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Overrides {
+    /// Directory containing the exported map JSON files
+    #[arg(long)]
+    pub maps_dir: Option<PathBuf>,
+
+    /// Directory with the built viewer (`npm run build` output)
+    #[arg(long)]
+    pub static_dir: Option<PathBuf>,
+
+    /// Directory for generated files such as minimaps
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Address to listen on; repeat to listen on several
+    #[arg(long = "listen", short)]
+    pub listen: Option<Vec<String>>,
+
+    /// Origin allowed by CORS; repeat for several, `*` allows any
+    #[arg(long = "cors-origin")]
+    pub cors_origins: Option<Vec<String>>,
+
+    /// Capacity of the WebSocket broadcast channel
+    #[arg(long)]
+    pub broadcast_capacity: Option<usize>,
+
+    /// Bearer token for uploading and deleting maps; uploads are disabled
+    /// without one. Prefer the config file or environment, as command lines
+    /// are visible to other users
+    #[arg(long)]
+    pub api_token: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub maps_dir: PathBuf,
+    pub static_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub listen: Vec<String>,
+    pub cors_origins: Vec<String>,
+    pub broadcast_capacity: usize,
+    pub api_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        // Anchored to the crate so the defaults don't depend on the working
+        // directory; the frontend is checked out next to it
+        let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let frontend_path = crate_dir.join("../threejs-terrain-viewer");
+        Config {
+            maps_dir: frontend_path.join("public/maps"),
+            // Serving the viewer source directly won't work for bare module imports
+            // (like 'three') without a bundler, so we serve the Vite build output
+            static_dir: frontend_path.join("dist"),
+            cache_dir: crate_dir.join("cache"),
+            listen: vec!["0.0.0.0:3000".to_string()],
+            cors_origins: vec!["*".to_string()],
+            broadcast_capacity: 100,
+            api_token: None,
+        }
+    }
+}
+
+impl Config {
+    fn apply(&mut self, overrides: Overrides) {
+        if let Some(maps_dir) = overrides.maps_dir {
+            self.maps_dir = maps_dir;
+        }
+        if let Some(static_dir) = overrides.static_dir {
+            self.static_dir = static_dir;
+        }
+        if let Some(cache_dir) = overrides.cache_dir {
+            self.cache_dir = cache_dir;
+        }
+        if let Some(listen) = overrides.listen {
+            self.listen = listen;
+        }
+        if let Some(cors_origins) = overrides.cors_origins {
+            self.cors_origins = cors_origins;
+        }
+        if let Some(capacity) = overrides.broadcast_capacity {
+            self.broadcast_capacity = capacity;
+        }
+        if let Some(token) = overrides.api_token {
+            self.api_token = Some(token);
+        }
+    }
+
+    /// Builds the effective configuration for `cli`.
+    pub fn load(cli: &Cli) -> Result<Config, String> {
+        let mut config = Config::default();
+
+        let config_path = cli
+            .config
+            .clone()
+            .or_else(|| env_var("CONFIG").map(PathBuf::from))
+            .or_else(|| {
+                [Path::new(""), Path::new(env!("CARGO_MANIFEST_DIR"))]
+                    .into_iter()
+                    .map(|dir| dir.join(DEFAULT_CONFIG_FILE))
+                    .find(|path| path.exists())
+            });
+        if let Some(path) = config_path {
+            config.apply(read_file(&path)?);
+        }
+
+        config.apply(cli.overrides.clone());
+        config.apply(from_env()?);
+
+        if config.listen.is_empty() {
+            return Err("at least one listen address is required".to_string());
+        }
+        if config.api_token.as_deref() == Some("") {
+            return Err("api_token must not be empty".to_string());
+        }
+        if config.broadcast_capacity == 0 {
+            return Err("broadcast capacity must be at least 1".to_string());
+        }
+        // Reject malformed origins before anything starts
+        let _ = config.cors_layer()?;
+        Ok(config)
+    }
+
+    /// `*` anywhere in the allow-list keeps the old permissive behaviour.
+    pub fn cors_layer(&self) -> Result<CorsLayer, String> {
+        if self.cors_origins.iter().any(|o| o == "*") {
+            return Ok(CorsLayer::permissive());
+        }
+        let origins = self
+            .cors_origins
+            .iter()
+            .map(|o| HeaderValue::from_str(o).map_err(|_| format!("invalid CORS origin {:?}", o)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(Any)
+            // A wildcard doesn't cover Authorization, which uploads need
+            .allow_headers(AllowHeaders::mirror_request()))
+    }
+}
+
+/// Reads a config file. Relative paths inside it are resolved against the
+/// file's own directory, so it works regardless of the working directory.
+fn read_file(path: &Path) -> Result<Overrides, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("could not read config {:?}: {}", path, e))?;
+    let mut overrides: Overrides =
+        toml::from_str(&text).map_err(|e| format!("invalid config {:?}: {}", path, e))?;
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    for dir in [
+        &mut overrides.maps_dir,
+        &mut overrides.static_dir,
+        &mut overrides.cache_dir,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if dir.is_relative() {
+            *dir = base.join(&*dir);
+        }
+    }
+    Ok(overrides)
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(format!("{}{}", ENV_PREFIX, name))
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+/// Comma-separated lists, e.g. `TERRAIN_SERVER_LISTEN=0.0.0.0:3000,[::]:3000`.
+fn env_list(name: &str) -> Option<Vec<String>> {
+    env_var(name).map(|v| {
+        v.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+fn from_env() -> Result<Overrides, String> {
+    let broadcast_capacity = match env_var("BROADCAST_CAPACITY") {
+        Some(v) => Some(v.parse().map_err(|_| {
+            format!(
+                "{}BROADCAST_CAPACITY must be a number, got {:?}",
+                ENV_PREFIX, v
+            )
+        })?),
+        None => None,
+    };
+    Ok(Overrides {
+        maps_dir: env_var("MAPS_DIR").map(PathBuf::from),
+        static_dir: env_var("STATIC_DIR").map(PathBuf::from),
+        cache_dir: env_var("CACHE_DIR").map(PathBuf::from),
+        listen: env_list("LISTEN"),
+        cors_origins: env_list("CORS_ORIGINS"),
+        broadcast_capacity,
+        api_token: env_var("API_TOKEN"),
+    })
+}
@@ -0,0 +1,368 @@
+//! Contour lines as GeoJSON.
+//!
+//! Marching squares runs over the mosaic rather than chunk by chunk, so lines
+//! continue across chunk borders without any matching of loose ends: every
+//! crossing is identified by the grid edge it lies on, and the two cells
+//! sharing that edge produce the same point.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{AppState, api::ApiError, mosaic::Mosaic};
+
+/// Upper bound on contour levels per request.
+const MAX_LEVELS: usize = 1000;
+
+/// Grid edge holding a crossing: `2 * node` for the edge to the next column,
+/// `2 * node + 1` for the edge to the next row.
+type EdgeId = usize;
+
+/// One contour at `elevation`, as `[x, z]` points. Closed rings repeat their
+/// first point at the end.
+pub struct Contour {
+    pub elevation: f32,
+    pub points: Vec<[f32; 2]>,
+}
+
+/// Crossing segments of one level, keyed by grid edge.
+struct Segments {
+    points: HashMap<EdgeId, [f32; 2]>,
+    neighbours: HashMap<EdgeId, Vec<EdgeId>>,
+}
+
+impl Segments {
+    fn add(&mut self, a: (EdgeId, [f32; 2]), b: (EdgeId, [f32; 2])) {
+        self.points.insert(a.0, a.1);
+        self.points.insert(b.0, b.1);
+        self.neighbours.entry(a.0).or_default().push(b.0);
+        self.neighbours.entry(b.0).or_default().push(a.0);
+    }
+
+    /// Joins segments into polylines: open lines are walked from their ends
+    /// first, whatever remains are closed rings.
+    fn into_lines(mut self) -> Vec<Vec<[f32; 2]>> {
+        let mut starts: Vec<EdgeId> = self
+            .neighbours
+            .iter()
+            .filter(|(_, n)| n.len() == 1)
+            .map(|(&edge, _)| edge)
+            .collect();
+        starts.sort_unstable();
+        let mut rest: Vec<EdgeId> = self.neighbours.keys().copied().collect();
+        rest.sort_unstable();
+        starts.extend(rest);
+
+        let mut lines = Vec::new();
+        for start in starts {
+            if self.neighbours.get(&start).is_none_or(|n| n.is_empty()) {
+                continue;
+            }
+            let mut line = vec![self.points[&start]];
+            let mut current = start;
+            while let Some(next) = self.neighbours.get_mut(&current).and_then(|n| n.pop()) {
+                if let Some(back) = self.neighbours.get_mut(&next)
+                    && let Some(i) = back.iter().position(|&e| e == current)
+                {
+                    back.swap_remove(i);
+                }
+                line.push(self.points[&next]);
+                current = next;
+            }
+            if line.len() >= 2 {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+}
+
+/// Contours of the mosaic at every multiple of `interval` between its lowest
+/// and highest point. Cells are visited once, each adding segments to the
+/// levels between its lowest and highest corner.
+pub fn contours(mosaic: &Mosaic, interval: f32) -> Result<Vec<Contour>, String> {
+    let (lo, hi) = (0..mosaic.rows)
+        .flat_map(|row| (0..mosaic.cols).filter_map(move |col| mosaic.get(col, row)))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), h| {
+            (lo.min(h), hi.max(h))
+        });
+    if lo > hi {
+        return Ok(Vec::new());
+    }
+    // In f64 and checked before converting, as tiny intervals overflow
+    let first = (lo as f64 / interval as f64).ceil();
+    let last = (hi as f64 / interval as f64).floor();
+    let count = (last - first + 1.0).max(0.0);
+    if count > MAX_LEVELS as f64 {
+        return Err(format!(
+            "interval {} gives {} levels between {} and {}, more than the limit of {}",
+            interval, count, lo, hi, MAX_LEVELS
+        ));
+    }
+    let (first, last) = (first as i64, last as i64);
+
+    let mut levels: Vec<Segments> = (first..=last)
+        .map(|_| Segments {
+            points: HashMap::new(),
+            neighbours: HashMap::new(),
+        })
+        .collect();
+    for row in 0..mosaic.rows.saturating_sub(1) {
+        for col in 0..mosaic.cols.saturating_sub(1) {
+            // Cells touching no-data are left open
+            let (Some(h00), Some(h10), Some(h11), Some(h01)) = (
+                mosaic.get(col, row),
+                mosaic.get(col + 1, row),
+                mosaic.get(col + 1, row + 1),
+                mosaic.get(col, row + 1),
+            ) else {
+                continue;
+            };
+            let heights = [h00, h10, h11, h01];
+            let min = heights.into_iter().fold(f32::INFINITY, f32::min);
+            let max = heights.into_iter().fold(f32::NEG_INFINITY, f32::max);
+            let k0 = ((min as f64 / interval as f64).floor() as i64).max(first);
+            let k1 = ((max as f64 / interval as f64).ceil() as i64).min(last);
+            for k in k0..=k1 {
+                let level = k as f32 * interval;
+                // Cells with every corner on one side have no crossing
+                if min < level && level <= max {
+                    march_cell(
+                        mosaic,
+                        col,
+                        row,
+                        heights,
+                        level,
+                        &mut levels[(k - first) as usize],
+                    );
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for (k, segments) in (first..=last).zip(levels) {
+        let elevation = k as f32 * interval;
+        for points in segments.into_lines() {
+            result.push(Contour { elevation, points });
+        }
+    }
+    Ok(result)
+}
+
+/// Adds the crossings of `level` through cell `(col, row)`, whose corner
+/// heights go round from `(col, row)`: `[h00, h10, h11, h01]`.
+fn march_cell(
+    mosaic: &Mosaic,
+    col: usize,
+    row: usize,
+    [h00, h10, h11, h01]: [f32; 4],
+    level: f32,
+    segments: &mut Segments,
+) {
+    let cols = mosaic.cols;
+    let position = |col: usize, row: usize| {
+        [
+            mosaic.min_x + col as f32 * mosaic.cell_size,
+            mosaic.min_z + row as f32 * mosaic.cell_size,
+        ]
+    };
+    // Where the level crosses the edge from node (c0, r0) to node (c1, r1)
+    let crossing = |(c0, r0): (usize, usize), h0: f32, (c1, r1): (usize, usize), h1: f32| {
+        let edge = if r0 == r1 {
+            2 * (r0 * cols + c0.min(c1))
+        } else {
+            2 * (r0.min(r1) * cols + c0) + 1
+        };
+        let t = (level - h0) / (h1 - h0);
+        let p0 = position(c0, r0);
+        let p1 = position(c1, r1);
+        (
+            edge,
+            [p0[0] + (p1[0] - p0[0]) * t, p0[1] + (p1[1] - p0[1]) * t],
+        )
+    };
+
+    let corners = [
+        ((col, row), h00),
+        ((col + 1, row), h10),
+        ((col + 1, row + 1), h11),
+        ((col, row + 1), h01),
+    ];
+    let case = corners
+        .iter()
+        .enumerate()
+        .fold(0, |case, (i, &(_, h))| case | ((h >= level) as usize) << i);
+    if case == 0 || case == 15 {
+        return;
+    }
+
+    // Side i runs from corner i to corner i + 1
+    let side = |i: usize| {
+        let (n0, h0) = corners[i];
+        let (n1, h1) = corners[(i + 1) % 4];
+        crossing(n0, h0, n1, h1)
+    };
+    let crossed: Vec<usize> = (0..4)
+        .filter(|&i| ((case >> i) & 1) != ((case >> ((i + 1) % 4)) & 1))
+        .collect();
+    if crossed.len() == 2 {
+        segments.add(side(crossed[0]), side(crossed[1]));
+    } else {
+        // Saddle: the cell centre decides which corners connect
+        let centre = (h00 + h10 + h11 + h01) / 4.0;
+        let first_above = case & 1 == 1;
+        if (centre >= level) == first_above {
+            // Corner 0's region joins corner 2's
+            segments.add(side(0), side(1));
+            segments.add(side(2), side(3));
+        } else {
+            segments.add(side(3), side(0));
+            segments.add(side(1), side(2));
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ContourQuery {
+    /// Height difference between neighbouring contours, in world units.
+    interval: f32,
+}
+
+#[derive(Serialize)]
+struct Geometry {
+    r#type: &'static str,
+    coordinates: Vec<[f32; 2]>,
+}
+
+#[derive(Serialize)]
+struct Properties {
+    elevation: f32,
+}
+
+#[derive(Serialize)]
+struct Feature {
+    r#type: &'static str,
+    geometry: Geometry,
+    properties: Properties,
+}
+
+/// GeoJSON in world units, with `[x, z]` positions.
+#[derive(Serialize)]
+pub struct FeatureCollection {
+    r#type: &'static str,
+    features: Vec<Feature>,
+}
+
+pub async fn get_contours(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<ContourQuery>,
+) -> Result<Json<FeatureCollection>, ApiError> {
+    if !(query.interval.is_finite() && query.interval > 0.0) {
+        return Err(ApiError::BadRequest(
+            "interval must be a positive number".to_string(),
+        ));
+    }
+
+    let map = state.map(&name).await?;
+    let contours = tokio::task::spawn_blocking(move || {
+        map.mosaic()
+            .and_then(|mosaic| contours(mosaic, query.interval).map_err(ApiError::BadRequest))
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Contour task failed".to_string()))??;
+    Ok(Json(FeatureCollection {
+        r#type: "FeatureCollection",
+        features: contours
+            .into_iter()
+            .map(|contour| Feature {
+                r#type: "Feature",
+                geometry: Geometry {
+                    r#type: "LineString",
+                    coordinates: contour.points,
+                },
+                properties: Properties {
+                    elevation: contour.elevation,
+                },
+            })
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{MapFile, TerrainChunk};
+
+    fn mosaic(heights: Vec<f32>) -> Mosaic {
+        let map = MapFile {
+            scene: "test".to_string(),
+            terrains: vec![TerrainChunk::from_heights("a", 0.0, 0.0, heights)],
+        };
+        Mosaic::new(&map).unwrap()
+    }
+
+    /// Lines at the first contour level above zero, each starting at its
+    /// smaller end so comparisons don't depend on walking direction.
+    fn contours_at(mosaic: &Mosaic, interval: f32) -> Vec<Vec<[f32; 2]>> {
+        let mut lines: Vec<Vec<[f32; 2]>> = contours(mosaic, interval)
+            .unwrap()
+            .into_iter()
+            .filter(|c| c.elevation == interval)
+            .map(|c| {
+                let mut points = c.points;
+                if points[points.len() - 1] < points[0] {
+                    points.reverse();
+                }
+                points
+            })
+            .collect();
+        lines.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        lines
+    }
+
+    #[test]
+    fn saddle_cell_connects_by_centre_height() {
+        // High corners at (0, 0) and (1, 1), centre at 0.5
+        let mosaic = mosaic(vec![1.0, 0.0, 0.0, 1.0]);
+        // At the centre's height the high corners join, cutting off the low ones
+        assert_eq!(
+            contours_at(&mosaic, 0.5),
+            [vec![[0.0, 0.5], [0.5, 1.0]], vec![[0.5, 0.0], [1.0, 0.5]]]
+        );
+        // Above it the high corners are cut off on their own
+        assert_eq!(
+            contours_at(&mosaic, 0.75),
+            [
+                vec![[0.0, 0.25], [0.25, 0.0]],
+                vec![[0.75, 1.0], [1.0, 0.75]],
+            ]
+        );
+    }
+
+    #[test]
+    fn peak_gets_a_closed_ring() {
+        #[rustfmt::skip]
+        let mosaic = mosaic(vec![
+            0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 1.0, 1.0, 0.0,
+            0.0, 1.0, 2.0, 1.0, 0.0,
+            0.0, 1.0, 1.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, 0.0,
+        ]);
+        let contours = contours(&mosaic, 1.5).unwrap();
+        assert_eq!(contours.len(), 1);
+
+        let ring = &contours[0];
+        assert_eq!(ring.elevation, 1.5);
+        assert_eq!(ring.points.len(), 5);
+        assert_eq!(ring.points[0], ring.points[4]);
+        let mut corners = ring.points[..4].to_vec();
+        corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(corners, [[1.5, 2.0], [2.0, 1.5], [2.0, 2.5], [2.5, 2.0]]);
+    }
+}
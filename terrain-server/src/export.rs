@@ -0,0 +1,466 @@
+//! Mesh exports for use outside the browser.
+//!
+//! glTF and OBJ keep the viewer's Y-up axes. STL and PLY are mostly consumed by
+//! slicers and scan tools, which expect Z up, so they are rotated into that
+//! frame: `(x, y, z)` becomes `(x, -z, y)`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{fmt::Write as _, sync::Arc};
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{MapFile, TerrainChunk},
+    mesh::TriangleMesh,
+};
+
+const GLB_MAGIC: u32 = 0x4654_6C67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A; // "JSON"
+const CHUNK_BIN: u32 = 0x004E_4942; // "BIN\0"
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+
+fn pad_to_four(buf: &mut Vec<u8>, pad: u8) {
+    while !buf.len().is_multiple_of(4) {
+        buf.push(pad);
+    }
+}
+
+/// Builds a binary glTF 2.0 file with one mesh node per chunk, named after
+/// the chunk and translated to where the viewer places it. glTF and Three.js
+/// are both right-handed and Y-up, so no axis conversion is needed.
+pub fn to_glb(map: &MapFile) -> Vec<u8> {
+    let mut bin: Vec<u8> = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+
+    let mut push_view = |bin: &mut Vec<u8>, bytes: &[u8], target: u32| {
+        let offset = bin.len();
+        bin.extend_from_slice(bytes);
+        pad_to_four(bin, 0);
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        buffer_views.len() - 1
+    };
+
+    for chunk in &map.terrains {
+        let mesh = chunk.mesh(1.0);
+        let (min, max) = mesh.bounds();
+
+        let positions: Vec<u8> = mesh
+            .positions
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let normals: Vec<u8> = mesh
+            .normals
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        // 16-bit indices cover everything up to resolution 256
+        let (indices, index_type): (Vec<u8>, u32) = if mesh.positions.len() <= u16::MAX as usize {
+            (
+                mesh.indices
+                    .iter()
+                    .flat_map(|&i| (i as u16).to_le_bytes())
+                    .collect(),
+                UNSIGNED_SHORT,
+            )
+        } else {
+            (
+                mesh.indices.iter().flat_map(|i| i.to_le_bytes()).collect(),
+                UNSIGNED_INT,
+            )
+        };
+
+        let position_view = push_view(&mut bin, &positions, ARRAY_BUFFER);
+        let normal_view = push_view(&mut bin, &normals, ARRAY_BUFFER);
+        let index_view = push_view(&mut bin, &indices, ELEMENT_ARRAY_BUFFER);
+
+        let first_accessor = accessors.len();
+        accessors.push(json!({
+            "bufferView": position_view,
+            "componentType": FLOAT,
+            "count": mesh.positions.len(),
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        accessors.push(json!({
+            "bufferView": normal_view,
+            "componentType": FLOAT,
+            "count": mesh.normals.len(),
+            "type": "VEC3",
+        }));
+        accessors.push(json!({
+            "bufferView": index_view,
+            "componentType": index_type,
+            "count": mesh.indices.len(),
+            "type": "SCALAR",
+        }));
+
+        meshes.push(json!({
+            "name": chunk.name,
+            "primitives": [{
+                "attributes": {
+                    "POSITION": first_accessor,
+                    "NORMAL": first_accessor + 1,
+                },
+                "indices": first_accessor + 2,
+                "material": 0,
+            }],
+        }));
+        nodes.push(json!({
+            "name": chunk.name,
+            "mesh": meshes.len() - 1,
+            "translation": chunk.center(),
+        }));
+    }
+
+    let children: Vec<usize> = (1..=nodes.len()).collect();
+    nodes.insert(0, json!({ "name": map.scene, "children": children }));
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "terrain-server" },
+        "scene": 0,
+        "scenes": [{ "name": map.scene, "nodes": [0] }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": [{
+            "name": "Terrain",
+            "pbrMetallicRoughness": {
+                // The viewer's 0x646cff
+                "baseColorFactor": [0.392, 0.424, 1.0, 1.0],
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+            "doubleSided": true,
+        }],
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": [{ "byteLength": bin.len() }],
+    });
+
+    let mut json_chunk = serde_json::to_vec(&document).expect("glTF document serializes");
+    pad_to_four(&mut json_chunk, b' ');
+
+    let total = 12 + 8 + json_chunk.len() + 8 + bin.len();
+    let mut out = Vec::with_capacity(total);
+    out.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+    out.extend_from_slice(&GLB_VERSION.to_le_bytes());
+    out.extend_from_slice(&(total as u32).to_le_bytes());
+    out.extend_from_slice(&(json_chunk.len() as u32).to_le_bytes());
+    out.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+    out.extend_from_slice(&json_chunk);
+    out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    out.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+    out.extend_from_slice(&bin);
+    out
+}
+
+fn z_up([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [x, -z, y]
+}
+
+fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if len > 0.0 { n.map(|c| c / len) } else { n }
+}
+
+/// Wavefront OBJ with one object per chunk, in world coordinates.
+pub fn to_obj(scene: &str, chunks: &[&TerrainChunk], vertical_scale: f32) -> Vec<u8> {
+    let meshes: Vec<TriangleMesh> = chunks
+        .iter()
+        .map(|c| c.world_mesh(vertical_scale))
+        .collect();
+    let objects: Vec<(&str, &TriangleMesh)> = chunks
+        .iter()
+        .map(|c| c.name.as_str())
+        .zip(&meshes)
+        .collect();
+    meshes_to_obj(scene, &objects)
+}
+
+/// Wavefront OBJ with one named object per mesh, positions as they are.
+pub fn meshes_to_obj(scene: &str, objects: &[(&str, &TriangleMesh)]) -> Vec<u8> {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {} exported by terrain-server", scene);
+
+    // OBJ indices are 1-based and global across objects
+    let mut base = 1;
+    for (name, mesh) in objects {
+        let _ = writeln!(out, "o {}", name);
+        for [x, y, z] in &mesh.positions {
+            let _ = writeln!(out, "v {} {} {}", x, y, z);
+        }
+        for [x, y, z] in &mesh.normals {
+            let _ = writeln!(out, "vn {} {} {}", x, y, z);
+        }
+        for [a, b, c] in mesh.triangles() {
+            let (a, b, c) = (a + base, b + base, c + base);
+            let _ = writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}");
+        }
+        base += mesh.positions.len() as u32;
+    }
+    out.into_bytes()
+}
+
+/// Binary STL, Z up.
+pub fn to_stl(scene: &str, chunks: &[&TerrainChunk], vertical_scale: f32) -> Vec<u8> {
+    let meshes: Vec<TriangleMesh> = chunks
+        .iter()
+        .map(|c| c.world_mesh(vertical_scale))
+        .collect();
+    let triangle_count: usize = meshes.iter().map(|m| m.indices.len() / 3).sum();
+
+    let mut out = Vec::with_capacity(84 + triangle_count * 50);
+    let mut header = [0u8; 80];
+    let title = format!("{} exported by terrain-server", scene);
+    let len = title.len().min(header.len());
+    header[..len].copy_from_slice(&title.as_bytes()[..len]);
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(triangle_count as u32).to_le_bytes());
+
+    for mesh in &meshes {
+        for tri in mesh.triangles() {
+            let [a, b, c] = tri.map(|i| z_up(mesh.positions[i as usize]));
+            for v in [face_normal(a, b, c), a, b, c] {
+                for component in v {
+                    out.extend_from_slice(&component.to_le_bytes());
+                }
+            }
+            out.extend_from_slice(&0u16.to_le_bytes());
+        }
+    }
+    out
+}
+
+/// Binary little-endian PLY with per-vertex normals, Z up.
+pub fn to_ply(scene: &str, chunks: &[&TerrainChunk], vertical_scale: f32) -> Vec<u8> {
+    let meshes: Vec<TriangleMesh> = chunks
+        .iter()
+        .map(|c| c.world_mesh(vertical_scale))
+        .collect();
+    let vertex_count: usize = meshes.iter().map(|m| m.positions.len()).sum();
+    let face_count: usize = meshes.iter().map(|m| m.indices.len() / 3).sum();
+
+    let mut out = format!(
+        "ply\n\
+         format binary_little_endian 1.0\n\
+         comment {} exported by terrain-server\n\
+         element vertex {}\n\
+         property float x\nproperty float y\nproperty float z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         element face {}\n\
+         property list uchar uint vertex_indices\n\
+         end_header\n",
+        scene, vertex_count, face_count
+    )
+    .into_bytes();
+
+    for mesh in &meshes {
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            for component in z_up(*p).into_iter().chain(z_up(*n)) {
+                out.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+    }
+    let mut base = 0;
+    for mesh in &meshes {
+        for tri in mesh.triangles() {
+            out.push(3);
+            for i in tri {
+                out.extend_from_slice(&(i + base).to_le_bytes());
+            }
+        }
+        base += mesh.positions.len() as u32;
+    }
+    out
+}
+
+pub fn attachment(body: Vec<u8>, content_type: &'static str, file_name: &str) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+pub async fn export_glb(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Response, ApiError> {
+    let map = state.map(&name).await?;
+    let glb = {
+        let map = map.clone();
+        tokio::task::spawn_blocking(move || to_glb(&map.data))
+            .await
+            .map_err(|_| ApiError::Internal("Export failed".to_string()))?
+    };
+    Ok(attachment(
+        glb,
+        "model/gltf-binary",
+        &format!("{}.glb", map.data.scene),
+    ))
+}
+
+#[derive(Clone, Copy)]
+enum MeshFormat {
+    Obj,
+    Stl,
+    Ply,
+}
+
+impl MeshFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            MeshFormat::Obj => "obj",
+            MeshFormat::Stl => "stl",
+            MeshFormat::Ply => "ply",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            MeshFormat::Obj => "model/obj",
+            MeshFormat::Stl => "model/stl",
+            MeshFormat::Ply => "application/octet-stream",
+        }
+    }
+
+    fn encode(self, scene: &str, chunks: &[&TerrainChunk], vertical_scale: f32) -> Vec<u8> {
+        match self {
+            MeshFormat::Obj => to_obj(scene, chunks, vertical_scale),
+            MeshFormat::Stl => to_stl(scene, chunks, vertical_scale),
+            MeshFormat::Ply => to_ply(scene, chunks, vertical_scale),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// Vertical exaggeration applied to world heights, 1 by default.
+    exaggeration: Option<f32>,
+}
+
+async fn export_mesh(
+    state: Arc<AppState>,
+    name: String,
+    chunk_name: Option<String>,
+    query: ExportQuery,
+    format: MeshFormat,
+) -> Result<Response, ApiError> {
+    let vertical_scale = query.exaggeration.unwrap_or(1.0);
+    if !(vertical_scale.is_finite() && vertical_scale > 0.0) {
+        return Err(ApiError::BadRequest(
+            "exaggeration must be a positive number".to_string(),
+        ));
+    }
+
+    let map = state.map(&name).await?;
+    let (index, file_stem) = match &chunk_name {
+        Some(chunk_name) => {
+            let index = map
+                .data
+                .chunk_index(chunk_name)
+                .ok_or_else(|| ApiError::chunk_not_found(&name, chunk_name))?;
+            (Some(index), format!("{}_{}", map.data.scene, chunk_name))
+        }
+        None => (None, map.data.scene.clone()),
+    };
+
+    let body = tokio::task::spawn_blocking(move || {
+        let chunks: Vec<&TerrainChunk> = match index {
+            Some(index) => vec![&map.data.terrains[index]],
+            None => map.data.terrains.iter().collect(),
+        };
+        format.encode(&map.data.scene, &chunks, vertical_scale)
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Export failed".to_string()))?;
+    Ok(attachment(
+        body,
+        format.content_type(),
+        &format!("{}.{}", file_stem, format.extension()),
+    ))
+}
+
+pub async fn export_obj(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    export_mesh(state, name, None, query, MeshFormat::Obj).await
+}
+
+pub async fn export_stl(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    export_mesh(state, name, None, query, MeshFormat::Stl).await
+}
+
+pub async fn export_ply(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    export_mesh(state, name, None, query, MeshFormat::Ply).await
+}
+
+pub async fn export_chunk_obj(
+    State(state): State<Arc<AppState>>,
+    Path((name, chunk)): Path<(String, String)>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    export_mesh(state, name, Some(chunk), query, MeshFormat::Obj).await
+}
+
+pub async fn export_chunk_stl(
+    State(state): State<Arc<AppState>>,
+    Path((name, chunk)): Path<(String, String)>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    export_mesh(state, name, Some(chunk), query, MeshFormat::Stl).await
+}
+
+pub async fn export_chunk_ply(
+    State(state): State<Arc<AppState>>,
+    Path((name, chunk)): Path<(String, String)>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    export_mesh(state, name, Some(chunk), query, MeshFormat::Ply).await
+}
@@ -0,0 +1,451 @@
+//! Builds map files from heightmap rasters, so hand-authored terrains can be
+//! served next to exported scenes.
+//!
+//! Accepted rasters are Unity's `.raw`/`.r16` exports (square, unsigned
+//! 16-bit samples without a header) and grayscale PNGs, preferably 16-bit.
+//! Samples are scaled from `0..=max` to `0..=maxHeight` and the raster is cut
+//! into square chunks that share their border samples, like Unity terrains.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use serde::Deserialize;
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path as FsPath, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{MapFile, TerrainChunk},
+    upload,
+    validate::write_atomically,
+};
+
+const DEFAULT_CHUNK_SIZE: u32 = 64;
+const MIN_CHUNK_SIZE: u32 = 2;
+const MAX_CHUNK_SIZE: u32 = 4096;
+/// Upper bound on the chunks in one imported map, e.g. a 4097×4097 raster
+/// in the default 64-cell chunks.
+const MAX_CHUNKS: usize = 4096;
+
+#[derive(Clone, Copy, Deserialize, clap::ValueEnum, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RasterFormat {
+    /// Headerless 16-bit samples (Unity `.raw`/`.r16`)
+    Raw,
+    /// Grayscale PNG
+    Png,
+}
+
+impl RasterFormat {
+    fn from_extension(path: &FsPath) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "raw" | "r16" => Some(RasterFormat::Raw),
+            "png" => Some(RasterFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, clap::ValueEnum, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteOrder {
+    /// Unity's "Windows" byte order
+    #[default]
+    Little,
+    /// Unity's "Mac" byte order
+    Big,
+}
+
+/// How a raster becomes a map. Shared by the `import` subcommand and the
+/// HTTP endpoint, where the fields are camelCase query parameters.
+#[derive(clap::Args, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportOptions {
+    /// Raster format; guessed from the file extension if left out
+    #[arg(long, value_enum)]
+    pub format: Option<RasterFormat>,
+
+    /// World size of the whole raster along X
+    #[arg(long)]
+    pub width: f32,
+
+    /// World size along Z (default: same as width)
+    #[arg(long)]
+    pub depth: Option<f32>,
+
+    /// World height of the largest sample value
+    #[arg(long)]
+    pub max_height: f32,
+
+    /// World position of the raster's first sample
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    #[serde(default)]
+    pub origin_x: f32,
+
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    #[serde(default)]
+    pub origin_y: f32,
+
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    #[serde(default)]
+    pub origin_z: f32,
+
+    /// Grid cells per chunk side; chunks get chunk_size + 1 samples
+    #[arg(long, default_value_t = DEFAULT_CHUNK_SIZE)]
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: u32,
+
+    /// Byte order of RAW files
+    #[arg(long, value_enum, default_value = "little")]
+    #[serde(default)]
+    pub byte_order: ByteOrder,
+
+    /// Treat the first raster row as the largest Z, as most image editors do
+    #[arg(long)]
+    #[serde(default)]
+    pub flip_z: bool,
+
+    /// Scene name written into the map (default: the map's file name)
+    #[arg(long)]
+    pub scene: Option<String>,
+}
+
+fn default_chunk_size() -> u32 {
+    DEFAULT_CHUNK_SIZE
+}
+
+/// Samples normalised to `0..=1`, row-major with rows along Z.
+struct Raster {
+    cols: usize,
+    rows: usize,
+    samples: Vec<f32>,
+}
+
+impl Raster {
+    fn decode_raw(bytes: &[u8], byte_order: ByteOrder) -> Result<Raster, String> {
+        if !bytes.len().is_multiple_of(2) {
+            return Err("RAW data has an odd number of bytes".to_string());
+        }
+        let count = bytes.len() / 2;
+        // RAW files carry no dimensions, but Unity's are always square
+        let side = (count as f64).sqrt().round() as usize;
+        if side * side != count {
+            return Err(format!(
+                "RAW data holds {} samples, which isn't a square heightmap",
+                count
+            ));
+        }
+        let samples = bytes
+            .chunks_exact(2)
+            .map(|pair| {
+                let pair = [pair[0], pair[1]];
+                let value = match byte_order {
+                    ByteOrder::Little => u16::from_le_bytes(pair),
+                    ByteOrder::Big => u16::from_be_bytes(pair),
+                };
+                value as f32 / u16::MAX as f32
+            })
+            .collect();
+        Ok(Raster {
+            cols: side,
+            rows: side,
+            samples,
+        })
+    }
+
+    fn decode_png(bytes: &[u8]) -> Result<Raster, String> {
+        let mut decoder = png::Decoder::new(Cursor::new(bytes));
+        // Bit depths below 8 are widened to 8
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| format!("invalid PNG: {}", e))?;
+        let (color, depth) = reader.output_color_type();
+        let channels = match color {
+            png::ColorType::Grayscale => 1,
+            // The alpha channel is ignored
+            png::ColorType::GrayscaleAlpha => 2,
+            other => {
+                return Err(format!(
+                    "PNG must be grayscale, got {:?}; convert it first",
+                    other
+                ));
+            }
+        };
+        let size = reader
+            .output_buffer_size()
+            .ok_or("PNG is too large".to_string())?;
+        let mut buf = vec![0; size];
+        let frame = reader
+            .next_frame(&mut buf)
+            .map_err(|e| format!("invalid PNG: {}", e))?;
+
+        let (cols, rows) = (frame.width as usize, frame.height as usize);
+        let mut samples = Vec::with_capacity(cols * rows);
+        for row in buf[..frame.buffer_size()].chunks_exact(frame.line_size) {
+            match depth {
+                png::BitDepth::Sixteen => samples.extend(
+                    row.chunks_exact(2 * channels)
+                        .take(cols)
+                        .map(|px| u16::from_be_bytes([px[0], px[1]]) as f32 / u16::MAX as f32),
+                ),
+                _ => samples.extend(
+                    row.chunks_exact(channels)
+                        .take(cols)
+                        .map(|px| px[0] as f32 / u8::MAX as f32),
+                ),
+            }
+        }
+        Ok(Raster {
+            cols,
+            rows,
+            samples,
+        })
+    }
+
+    /// Bilinearly interpolated sample at fractional grid position `(u, v)`.
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        let u = u.clamp(0.0, (self.cols - 1) as f32);
+        let v = v.clamp(0.0, (self.rows - 1) as f32);
+        let col0 = u.floor() as usize;
+        let row0 = v.floor() as usize;
+        let col1 = (col0 + 1).min(self.cols - 1);
+        let row1 = (row0 + 1).min(self.rows - 1);
+        let fu = u - col0 as f32;
+        let fv = v - row0 as f32;
+        let at = |col: usize, row: usize| self.samples[row * self.cols + col];
+        let top = at(col0, row0) * (1.0 - fu) + at(col1, row0) * fu;
+        let bottom = at(col0, row1) * (1.0 - fu) + at(col1, row1) * fu;
+        top * (1.0 - fv) + bottom * fv
+    }
+}
+
+/// Builds a map from raster `bytes`. `format` must be known by now.
+pub fn build_map(
+    bytes: &[u8],
+    format: RasterFormat,
+    options: &ImportOptions,
+    scene: &str,
+) -> Result<MapFile, String> {
+    let width = options.width;
+    let depth = options.depth.unwrap_or(width);
+    for (name, value) in [
+        ("width", width),
+        ("depth", depth),
+        ("maxHeight", options.max_height),
+    ] {
+        if !(value.is_finite() && value > 0.0) {
+            return Err(format!("{} must be a positive number", name));
+        }
+    }
+    for (name, value) in [
+        ("originX", options.origin_x),
+        ("originY", options.origin_y),
+        ("originZ", options.origin_z),
+    ] {
+        if !value.is_finite() {
+            return Err(format!("{} must be a finite number", name));
+        }
+    }
+    let chunk_size = options.chunk_size as usize;
+    if !(MIN_CHUNK_SIZE as usize..=MAX_CHUNK_SIZE as usize).contains(&chunk_size) {
+        return Err(format!(
+            "chunkSize must be between {} and {}",
+            MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+        ));
+    }
+
+    let raster = match format {
+        RasterFormat::Raw => Raster::decode_raw(bytes, options.byte_order)?,
+        RasterFormat::Png => Raster::decode_png(bytes)?,
+    };
+    if raster.cols < 2 || raster.rows < 2 {
+        return Err("raster must be at least 2×2 samples".to_string());
+    }
+
+    // Grids that don't divide into whole chunks are stretched to the next
+    // multiple of the chunk size, which keeps the world size. Unity's
+    // 2^n + 1 heightmaps with power-of-two chunk sizes are copied exactly.
+    let chunks_x = (raster.cols - 1).div_ceil(chunk_size);
+    let chunks_z = (raster.rows - 1).div_ceil(chunk_size);
+    if chunks_x * chunks_z > MAX_CHUNKS {
+        return Err(format!(
+            "a {}×{} raster would make {} chunks, more than the limit of {}; use a larger chunkSize",
+            raster.cols,
+            raster.rows,
+            chunks_x * chunks_z,
+            MAX_CHUNKS
+        ));
+    }
+    let scale_u = (raster.cols - 1) as f32 / (chunks_x * chunk_size) as f32;
+    let scale_v = (raster.rows - 1) as f32 / (chunks_z * chunk_size) as f32;
+    let chunk_width = width / chunks_x as f32;
+    let chunk_depth = depth / chunks_z as f32;
+    let res = chunk_size + 1;
+
+    let mut terrains = Vec::with_capacity(chunks_x * chunks_z);
+    for cz in 0..chunks_z {
+        for cx in 0..chunks_x {
+            let mut height_map = Vec::with_capacity(res * res);
+            for row in 0..res {
+                let mut v = (cz * chunk_size + row) as f32 * scale_v;
+                if options.flip_z {
+                    v = (raster.rows - 1) as f32 - v;
+                }
+                for col in 0..res {
+                    let u = (cx * chunk_size + col) as f32 * scale_u;
+                    height_map.push(raster.sample(u, v) * options.max_height);
+                }
+            }
+            terrains.push(TerrainChunk {
+                name: format!("Terrain_{}_{}", cx, cz),
+                x: options.origin_x + cx as f32 * chunk_width,
+                y: options.origin_y,
+                z: options.origin_z + cz as f32 * chunk_depth,
+                width: chunk_width,
+                depth: chunk_depth,
+                max_height: options.max_height,
+                resolution: res as u32,
+                height_map,
+            });
+        }
+    }
+
+    let map = MapFile {
+        scene: scene.to_string(),
+        terrains,
+    };
+    map.validate().map_err(|e| e.to_string())?;
+    Ok(map)
+}
+
+/// `terrain-server import`: converts `input` and writes the map to `output`,
+/// by default next to the input with a `.json` extension.
+pub fn run(input: &FsPath, output: Option<&FsPath>, options: &ImportOptions) -> ExitCode {
+    let Some(format) = options
+        .format
+        .or_else(|| RasterFormat::from_extension(input))
+    else {
+        eprintln!(
+            "Error: can't tell the format of {:?} from its extension; pass --format",
+            input
+        );
+        return ExitCode::from(2);
+    };
+    let output: PathBuf = output
+        .map(FsPath::to_path_buf)
+        .unwrap_or_else(|| input.with_extension("json"));
+    let scene = options.scene.clone().unwrap_or_else(|| {
+        output
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+
+    let bytes = match fs::read(input) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Error reading {:?}: {}", input, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let map = match build_map(&bytes, format, options, &scene) {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("Error importing {:?}: {}", input, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let json = serde_json::to_vec(&map).expect("maps always serialize");
+    // Never leave a half-written map where the server might load it
+    if let Err(e) = write_atomically(&output, &json) {
+        eprintln!("Error writing {:?}: {}", output, e);
+        return ExitCode::FAILURE;
+    }
+    let first = &map.terrains[0];
+    println!(
+        "Wrote {:?}: {} chunks of {}×{} samples",
+        output,
+        map.terrains.len(),
+        first.resolution,
+        first.resolution
+    );
+    ExitCode::SUCCESS
+}
+
+/// `POST /api/maps/{name}/import` with the raster as the body. Authenticated
+/// and published like an upload.
+pub async fn import_map(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(options): Query<ImportOptions>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    upload::authorize(&state, &headers)?;
+    let file_name = upload::file_name(&name)?;
+    let format = options
+        .format
+        .ok_or_else(|| ApiError::BadRequest("format is required: raw or png".to_string()))?;
+    let scene = options
+        .scene
+        .clone()
+        .unwrap_or_else(|| crate::map::map_key(&file_name).to_string());
+
+    let map = tokio::task::spawn_blocking(move || build_map(&body, format, &options, &scene))
+        .await
+        .map_err(|_| ApiError::Internal("Import task failed".to_string()))?
+        .map_err(ApiError::BadRequest)?;
+    let json = serde_json::to_vec(&map).expect("maps always serialize");
+    upload::store(&state, file_name, Bytes::from(json)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(chunk_size: u32) -> ImportOptions {
+        serde_json::from_value(serde_json::json!({
+            "width": 8.0,
+            "maxHeight": 10.0,
+            "chunkSize": chunk_size,
+        }))
+        .unwrap()
+    }
+
+    /// Square little-endian RAW raster with `side` samples per side.
+    fn raw(side: usize) -> Vec<u8> {
+        (0..side * side)
+            .flat_map(|i| ((i % side * 1000) as u16).to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn chunks_share_their_border_samples() {
+        let map = build_map(&raw(5), RasterFormat::Raw, &options(2), "test").unwrap();
+        assert_eq!(map.terrains.len(), 4);
+        let (a, b) = (&map.terrains[0], &map.terrains[1]);
+        assert_eq!((a.resolution, a.width, b.x), (3, 4.0, 4.0));
+        for row in 0..3 {
+            assert_eq!(a.sample(2, row), b.sample(0, row));
+        }
+    }
+
+    #[test]
+    fn chunk_size_and_count_are_limited() {
+        assert!(build_map(&raw(5), RasterFormat::Raw, &options(1), "test").is_err());
+        assert!(build_map(&raw(129), RasterFormat::Raw, &options(2), "test").is_ok());
+        let err = build_map(&raw(131), RasterFormat::Raw, &options(2), "test").unwrap_err();
+        assert!(err.contains("4225 chunks"), "{}", err);
+    }
+}
@@ -0,0 +1,126 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{LoadedMap, TerrainChunk},
+    meta::MapMeta,
+};
+
+/// Axis-aligned bounding box in world space.
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: [0, 1, 2].map(|i| self.min[i].min(other.min[i])),
+            max: [0, 1, 2].map(|i| self.max[i].max(other.max[i])),
+        }
+    }
+}
+
+impl TerrainChunk {
+    /// Lowest and highest sample, relative to the chunk's `y`.
+    pub fn height_range(&self) -> (f32, f32) {
+        self.height_map
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            })
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let (lo, hi) = self.height_range();
+        Aabb {
+            min: [self.x, self.y + lo, self.z],
+            max: [self.x + self.width, self.y + hi, self.z + self.depth],
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkInfo {
+    name: String,
+    resolution: u32,
+    bounds: Aabb,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapInfo {
+    scene: String,
+    file: String,
+    bounds: Aabb,
+    chunk_count: usize,
+    total_vertices: usize,
+    min_height: f32,
+    max_height: f32,
+    mean_height: f32,
+    /// From the map's `.meta.toml`, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<MapMeta>,
+    chunks: Vec<ChunkInfo>,
+}
+
+impl MapInfo {
+    pub fn new(map: &LoadedMap) -> Self {
+        let chunks: Vec<ChunkInfo> = map
+            .data
+            .terrains
+            .iter()
+            .map(|chunk| ChunkInfo {
+                name: chunk.name.clone(),
+                resolution: chunk.resolution,
+                bounds: chunk.bounds(),
+            })
+            .collect();
+        // Maps are validated on load, so there is always at least one chunk
+        let bounds = chunks
+            .iter()
+            .map(|c| c.bounds)
+            .reduce(Aabb::union)
+            .expect("map has no terrains");
+
+        let mut total_vertices = 0;
+        let mut sum = 0.0f64;
+        for chunk in &map.data.terrains {
+            total_vertices += chunk.height_map.len();
+            sum += chunk
+                .height_map
+                .iter()
+                .map(|&h| (chunk.y + h) as f64)
+                .sum::<f64>();
+        }
+
+        MapInfo {
+            scene: map.data.scene.clone(),
+            file: map.file_name.clone(),
+            bounds,
+            chunk_count: chunks.len(),
+            total_vertices,
+            min_height: bounds.min[1],
+            max_height: bounds.max[1],
+            mean_height: (sum / total_vertices as f64) as f32,
+            meta: map.meta.clone(),
+            chunks,
+        }
+    }
+}
+
+pub async fn get_info(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<MapInfo>, ApiError> {
+    let map = state.map(&name).await?;
+    Ok(Json(MapInfo::new(&map)))
+}
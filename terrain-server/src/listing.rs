@@ -0,0 +1,210 @@
+//! The map list: scene names parsed into their parts, with search, sorting
+//! and pagination.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, sync::Arc};
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{LoadedMap, map_key},
+    minimap,
+};
+
+/// A scene name such as `ps0_10_cave4`: a prefix, the scene's index and a
+/// descriptive part. Names that don't follow the pattern only have a name.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SceneName {
+    pub prefix: Option<String>,
+    pub index: Option<u32>,
+    pub name: String,
+}
+
+impl SceneName {
+    pub fn parse(stem: &str) -> Self {
+        let mut parts = stem.splitn(3, '_');
+        if let (Some(prefix), Some(index), Some(rest)) = (parts.next(), parts.next(), parts.next())
+            && let Ok(index) = index.parse::<u32>()
+        {
+            return SceneName {
+                prefix: Some(prefix.to_string()),
+                index: Some(index),
+                name: humanize(rest),
+            };
+        }
+        SceneName {
+            prefix: None,
+            index: None,
+            name: humanize(stem),
+        }
+    }
+}
+
+/// `mount_magma` → `Mount Magma`, `cave4` → `Cave 4`.
+fn humanize(raw: &str) -> String {
+    raw.split(['_', '-', ' '])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            // Trailing numbers become their own word; all-digit words stay whole
+            let prefix = word.trim_end_matches(|c: char| c.is_ascii_digit());
+            let split = if prefix.is_empty() {
+                word.len()
+            } else {
+                prefix.len()
+            };
+            let (text, number) = word.split_at(split);
+            let mut chars = text.chars();
+            let mut out: String = chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect())
+                .unwrap_or_default();
+            if !number.is_empty() {
+                out.push(' ');
+                out.push_str(number);
+            }
+            out
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapEntry {
+    file: String,
+    prefix: Option<String>,
+    index: Option<u32>,
+    name: String,
+    /// Display name and tags from the map's `.meta.toml`.
+    display_name: Option<String>,
+    tags: Vec<String>,
+    /// Size of the map file in bytes.
+    size: usize,
+    chunk_count: usize,
+    thumbnail: String,
+}
+
+impl MapEntry {
+    fn new(map: &LoadedMap) -> Self {
+        let scene = SceneName::parse(map_key(&map.file_name));
+        MapEntry {
+            file: map.file_name.clone(),
+            prefix: scene.prefix,
+            index: scene.index,
+            name: scene.name,
+            display_name: map.meta.as_ref().and_then(|m| m.display_name.clone()),
+            tags: map
+                .meta
+                .as_ref()
+                .map(|m| m.tags.clone())
+                .unwrap_or_default(),
+            size: map.assets.file_size(),
+            chunk_count: map.data.terrains.len(),
+            thumbnail: minimap::thumbnail_url(map),
+        }
+    }
+
+    /// What the viewer shows: the display name if set, else the parsed name.
+    fn title(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [self.file.as_str(), &self.name, self.title()]
+            .into_iter()
+            .chain(self.tags.iter().map(String::as_str))
+            .any(|text| text.to_lowercase().contains(&query))
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Indexed scenes first, by index, then the rest by file name.
+    fn cmp_index(&self, other: &Self) -> Ordering {
+        match (self.index, other.index) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then_with(|| self.file.cmp(&other.file))
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Index,
+    Name,
+    Size,
+    Chunks,
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    /// Case-insensitive search in file and display names and tags.
+    q: Option<String>,
+    /// Only maps with this tag.
+    tag: Option<String>,
+    #[serde(default)]
+    sort: SortKey,
+    offset: Option<usize>,
+    /// All matching maps by default.
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct MapList {
+    /// Matching maps before pagination.
+    total: usize,
+    offset: usize,
+    maps: Vec<MapEntry>,
+}
+
+pub async fn list_maps(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<MapList>, ApiError> {
+    if query.limit == Some(0) {
+        return Err(ApiError::BadRequest("limit must be at least 1".to_string()));
+    }
+
+    let catalog = state.maps.read().await;
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    let mut maps: Vec<MapEntry> = catalog
+        .maps
+        .values()
+        .map(|map| MapEntry::new(map))
+        .filter(|entry| search.is_none_or(|q| entry.matches(q)))
+        .filter(|entry| query.tag.as_deref().is_none_or(|tag| entry.has_tag(tag)))
+        .collect();
+    drop(catalog);
+
+    // Ties keep index order
+    maps.sort_by(|a, b| {
+        match query.sort {
+            SortKey::Index => Ordering::Equal,
+            SortKey::Name => a.title().to_lowercase().cmp(&b.title().to_lowercase()),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Chunks => a.chunk_count.cmp(&b.chunk_count),
+        }
+        .then_with(|| a.cmp_index(b))
+    });
+
+    let total = maps.len();
+    let offset = query.offset.unwrap_or(0).min(total);
+    let limit = query.limit.unwrap_or(total);
+    let maps = maps.into_iter().skip(offset).take(limit).collect();
+    Ok(Json(MapList {
+        total,
+        offset,
+        maps,
+    }))
+}
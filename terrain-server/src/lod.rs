@@ -0,0 +1,113 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use std::sync::Arc;
+
+use crate::{AppState, api::ApiError, map::TerrainChunk};
+
+/// Downsampled copies of a chunk. `levels[0]` has roughly half the original
+/// resolution, and each further level halves it again down to a single quad.
+pub struct LodPyramid {
+    pub levels: Vec<TerrainChunk>,
+}
+
+impl LodPyramid {
+    pub fn new(chunk: &TerrainChunk) -> Self {
+        let mut levels: Vec<TerrainChunk> = Vec::new();
+        loop {
+            let source = levels.last().unwrap_or(chunk);
+            if source.resolution <= 2 {
+                break;
+            }
+            let next = downsample(source);
+            levels.push(next);
+        }
+        LodPyramid { levels }
+    }
+}
+
+/// Halves a chunk's grid, going from `r` to `ceil((r - 1) / 2) + 1` samples
+/// per side (33 → 17 → 9 …).
+///
+/// Border samples are taken straight from the source border, so two chunks
+/// that share an edge at full resolution still share it at every level.
+/// Interior samples are smoothed with a 3×3 tent filter to avoid aliasing.
+fn downsample(chunk: &TerrainChunk) -> TerrainChunk {
+    let res = chunk.resolution as usize;
+    let new_res = (res - 1).div_ceil(2) + 1;
+    let scale = (res - 1) as f32 / (new_res - 1) as f32;
+
+    let mut height_map = Vec::with_capacity(new_res * new_res);
+    for row in 0..new_res {
+        for col in 0..new_res {
+            let u = col as f32 * scale;
+            let v = row as f32 * scale;
+            let border = row == 0 || col == 0 || row == new_res - 1 || col == new_res - 1;
+            let h = if border {
+                // Exact for power-of-two-plus-one grids, where u and v are integers
+                chunk.sample_bilinear(u, v)
+            } else {
+                // scale >= 1, so the 3×3 neighbourhood stays inside the grid
+                let c = u.round() as usize;
+                let r = v.round() as usize;
+                let mut sum = 0.0;
+                for (dr, wr) in [(0, 1.0), (1, 2.0), (2, 1.0)] {
+                    for (dc, wc) in [(0, 1.0), (1, 2.0), (2, 1.0)] {
+                        sum += chunk.sample(c + dc - 1, r + dr - 1) * wr * wc;
+                    }
+                }
+                sum / 16.0
+            };
+            height_map.push(h);
+        }
+    }
+
+    TerrainChunk {
+        resolution: new_res as u32,
+        height_map,
+        ..chunk.clone_header()
+    }
+}
+
+impl TerrainChunk {
+    /// Copy of everything but the heightmap.
+    pub fn clone_header(&self) -> TerrainChunk {
+        TerrainChunk {
+            name: self.name.clone(),
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            width: self.width,
+            depth: self.depth,
+            max_height: self.max_height,
+            resolution: self.resolution,
+            height_map: Vec::new(),
+        }
+    }
+}
+
+/// Level 0 is the chunk as exported; level `n` is `LodPyramid::levels[n - 1]`.
+pub async fn get_chunk_lod(
+    State(state): State<Arc<AppState>>,
+    Path((name, chunk_name, level)): Path<(String, String, usize)>,
+) -> Result<Json<TerrainChunk>, ApiError> {
+    let map = state.map(&name).await?;
+    let index = map
+        .data
+        .chunk_index(&chunk_name)
+        .ok_or_else(|| ApiError::chunk_not_found(&name, &chunk_name))?;
+
+    let pyramid = &map.lods[index];
+    let chunk = match level {
+        0 => map.data.terrains[index].clone(),
+        n => pyramid.levels.get(n - 1).cloned().ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Chunk {} has LOD levels 0..={}",
+                chunk_name,
+                pyramid.levels.len()
+            ))
+        })?,
+    };
+    Ok(Json(chunk))
+}
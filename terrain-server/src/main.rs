@@ -1,28 +1,73 @@
 use axum::{
     Json, Router,
+    extract::DefaultBodyLimit,
     extract::State,
     extract::ws::{WebSocket, WebSocketUpgrade},
     response::Response,
     routing::get,
     routing::post,
+    routing::put,
 };
+use clap::Parser;
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf, sync::Arc};
+use std::{path::PathBuf, process::ExitCode, sync::Arc};
 use tokio::sync::{RwLock, broadcast};
-use tower_http::{cors::CorsLayer, services::ServeDir};
+use tower_http::services::ServeDir;
+
+mod api;
+mod assets;
+mod binary;
+mod config;
+mod contours;
+mod export;
+mod import;
+mod info;
+mod listing;
+mod lod;
+mod map;
+mod mesh;
+mod meta;
+mod minimap;
+mod mosaic;
+mod navmesh;
+mod pathfind;
+mod profile;
+mod raycast;
+mod sampling;
+mod seams;
+mod spatial;
+mod upload;
+mod validate;
+mod viewshed;
+mod watch;
+
+use api::ApiError;
+use config::{Cli, Command, Config};
+use map::{InvalidMap, LoadedMap, MapCatalog};
 
 #[tokio::main]
-async fn main() {
-    // Determine paths relative to where the binary is run (usually terrain-server root)
-    // We assume the frontend is in ../threejs-terrain-viewer
-    let frontend_path = PathBuf::from("../threejs-terrain-viewer");
-    let maps_path = frontend_path.join("public/maps");
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Validate { path, fix }) => return validate::run(path, *fix),
+        Some(Command::Import {
+            input,
+            output,
+            options,
+        }) => return import::run(input, output.as_deref(), options),
+        None => {}
+    }
 
-    // In a real build, we might serve from ./dist, but for now let's serve the source
-    // Note: Serving source directly won't work for bare module imports (like 'three') without a bundler/Vite.
-    // So we will assume the user runs 'npm run build' and we serve 'dist'.
-    let dist_path = frontend_path.join("dist");
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let maps_path = config.maps_dir.clone();
+    let dist_path = config.static_dir.clone();
 
     // Check if dist exists, otherwise warn
     if !dist_path.exists() {
@@ -32,29 +77,124 @@ async fn main() {
         );
     }
 
+    // Parse and validate every map up front so broken exports are reported here
+    // instead of failing in the browser
+    let catalog = MapCatalog::load_dir(&maps_path);
+    let chunk_count: usize = catalog.maps.values().map(|m| m.data.terrains.len()).sum();
+    println!(
+        "Loaded {} maps with {} terrain chunks from {:?} ({} invalid)",
+        catalog.maps.len(),
+        chunk_count,
+        maps_path,
+        catalog.invalid.len()
+    );
+
     // Create broadcast channel for player updates
-    let (tx, _) = broadcast::channel::<PlayerUpdate>(100);
+    let (tx, _) = broadcast::channel::<PlayerUpdate>(config.broadcast_capacity);
+
+    let state = Arc::new(AppState {
+        maps_dir: maps_path,
+        cache_dir: config.cache_dir.clone(),
+        api_token: config.api_token.clone(),
+        maps: Arc::new(RwLock::new(catalog)),
+        players: Arc::new(RwLock::new(Vec::new())),
+        tx,
+    });
+
+    // Re-parse maps as they are re-exported and tell connected clients
+    let _watcher = match watch::spawn(state.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            eprintln!("Hot reload disabled, could not watch maps directory: {}", e);
+            None
+        }
+    };
 
     let app = Router::new()
-        .route("/api/maps", get(list_maps))
+        .route("/api/maps", get(listing::list_maps))
+        .route("/api/invalid-maps", get(list_invalid_maps))
+        .route(
+            "/api/maps/{name}",
+            put(upload::put_map)
+                .delete(upload::delete_map)
+                .layer(DefaultBodyLimit::max(upload::MAX_UPLOAD_BYTES)),
+        )
+        .route(
+            "/api/maps/{name}/import",
+            post(import::import_map).layer(DefaultBodyLimit::max(upload::MAX_UPLOAD_BYTES)),
+        )
+        .route("/api/maps/{name}/height", get(sampling::get_height))
+        .route("/api/maps/{name}/info", get(info::get_info))
+        .route("/api/maps/{name}/chunks", get(spatial::get_chunks))
+        .route("/api/maps/{name}/contours", get(contours::get_contours))
+        .route("/api/maps/{name}/minimap.png", get(minimap::get_minimap))
+        .route("/api/maps/{name}/navmesh", get(navmesh::get_navmesh))
+        .route(
+            "/api/maps/{name}/navmesh.obj",
+            get(navmesh::export_navmesh_obj),
+        )
+        .route("/api/maps/{name}/los", post(raycast::line_of_sight))
+        .route("/api/maps/{name}/raycast", post(raycast::raycast))
+        .route("/api/maps/{name}/path", post(pathfind::find_path))
+        .route("/api/maps/{name}/profile", post(profile::get_profile))
+        .route("/api/maps/{name}/viewshed", post(viewshed::get_viewshed))
+        .route("/api/maps/{name}/region", get(mosaic::get_region))
+        .route("/api/maps/{name}/seams", get(seams::get_seams))
+        .route("/api/maps/{name}/stitched", get(seams::get_stitched))
+        .route("/api/maps/{name}/export.glb", get(export::export_glb))
+        .route("/api/maps/{name}/export.obj", get(export::export_obj))
+        .route("/api/maps/{name}/export.stl", get(export::export_stl))
+        .route("/api/maps/{name}/export.ply", get(export::export_ply))
+        .route(
+            "/api/maps/{name}/chunks/{chunk}/lod/{level}",
+            get(lod::get_chunk_lod),
+        )
+        .route(
+            "/api/maps/{name}/chunks/{chunk}/export.obj",
+            get(export::export_chunk_obj),
+        )
+        .route(
+            "/api/maps/{name}/chunks/{chunk}/export.stl",
+            get(export::export_chunk_stl),
+        )
+        .route(
+            "/api/maps/{name}/chunks/{chunk}/export.ply",
+            get(export::export_chunk_ply),
+        )
         .route("/api/players", get(get_players))
         .route("/api/players", post(create_player))
         .route("/api/players/move", post(move_player))
         .route("/api/players/clear", post(clear_players))
         .route("/ws", get(websocket_handler))
-        .nest_service("/maps", ServeDir::new(maps_path.clone()))
+        .route("/maps/{file}", get(assets::serve_map))
         .fallback_service(ServeDir::new(dist_path))
-        .layer(CorsLayer::permissive())
-        .with_state(Arc::new(AppState {
-            maps_dir: maps_path,
-            players: Arc::new(RwLock::new(Vec::new())),
-            tx,
-        }));
-
-    let addr = "0.0.0.0:3000";
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    println!("Server running on http://localhost:3000");
-    axum::serve(listener, app).await.unwrap();
+        .layer(config.cors_layer().expect("validated in Config::load"))
+        .with_state(state);
+
+    let mut servers = Vec::new();
+    for addr in &config.listen {
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Error: could not listen on {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        };
+        match listener.local_addr() {
+            Ok(local) => println!("Server running on http://{}", local),
+            Err(_) => println!("Server running on http://{}", addr),
+        }
+        let app = app.clone();
+        servers.push(tokio::spawn(
+            async move { axum::serve(listener, app).await },
+        ));
+    }
+    for server in servers {
+        if let Ok(Err(e)) = server.await {
+            eprintln!("Server error: {}", e);
+        }
+    }
+    ExitCode::SUCCESS
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -63,7 +203,9 @@ struct Player {
     x: f32,
     z: f32,
     #[serde(skip_serializing_if = "Option::is_none")]
-    y: Option<f32>, // Will be calculated on frontend
+    map: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<f32>, // Sampled from the player's map, if it covers (x, z)
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -72,61 +214,56 @@ enum PlayerUpdate {
     #[serde(rename = "player_created")]
     Created { player: Player },
     #[serde(rename = "player_moved")]
-    Moved { id: String, x: f32, z: f32 },
+    Moved {
+        id: String,
+        x: f32,
+        z: f32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        y: Option<f32>,
+    },
     #[serde(rename = "player_removed")]
     Removed { id: String },
     #[serde(rename = "all_cleared")]
     AllCleared,
     #[serde(rename = "initial_state")]
     InitialState { players: Vec<Player> },
+    #[serde(rename = "map_added")]
+    MapAdded { map: String },
+    #[serde(rename = "map_updated")]
+    MapUpdated { map: String },
+    #[serde(rename = "map_removed")]
+    MapRemoved { map: String },
 }
 
 #[derive(Clone)]
 struct AppState {
     maps_dir: PathBuf,
+    cache_dir: PathBuf,
+    api_token: Option<String>,
+    maps: Arc<RwLock<MapCatalog>>,
     players: Arc<RwLock<Vec<Player>>>,
     tx: broadcast::Sender<PlayerUpdate>,
 }
 
-async fn list_maps(State(state): State<Arc<AppState>>) -> Json<Vec<String>> {
-    let mut maps = Vec::new();
-    match fs::read_dir(&state.maps_dir) {
-        Ok(entries) => {
-            for entry in entries.flatten() {
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_file() {
-                        if let Some(name) = entry.file_name().to_str() {
-                            if name.ends_with(".json") {
-                                maps.push(name.to_string());
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Err(e) => {
-            eprintln!("Error reading maps directory {:?}: {}", state.maps_dir, e);
-        }
+impl AppState {
+    async fn map(&self, name: &str) -> Result<Arc<LoadedMap>, ApiError> {
+        self.maps
+            .read()
+            .await
+            .get(name)
+            .ok_or_else(|| ApiError::map_not_found(name))
     }
-    // Sort by extracted number
-    maps.sort_by(|a, b| {
-        let extract_num = |s: &str| -> Option<u32> {
-            s.split('_')
-                .nth(1)
-                .and_then(|part| part.parse::<u32>().ok())
-        };
 
-        let num_a = extract_num(a);
-        let num_b = extract_num(b);
+    /// Terrain height under `(x, z)` on `map`, used to place players server-side.
+    async fn height_at(&self, map: Option<&str>, x: f32, z: f32) -> Option<f32> {
+        let map = self.maps.read().await.get(map?)?;
+        map.data.height_at(x, z)
+    }
+}
 
-        match (num_a, num_b) {
-            (Some(na), Some(nb)) => na.cmp(&nb),
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => a.cmp(b),
-        }
-    });
-    Json(maps)
+async fn list_invalid_maps(State(state): State<Arc<AppState>>) -> Json<Vec<InvalidMap>> {
+    let catalog = state.maps.read().await;
+    Json(catalog.invalid.clone())
 }
 
 async fn get_players(State(state): State<Arc<AppState>>) -> Json<Vec<Player>> {
@@ -138,19 +275,22 @@ async fn get_players(State(state): State<Arc<AppState>>) -> Json<Vec<Player>> {
 struct CreatePlayerRequest {
     x: f32,
     z: f32,
+    map: Option<String>,
 }
 
 async fn create_player(
     State(state): State<Arc<AppState>>,
     Json(req): Json<CreatePlayerRequest>,
 ) -> Json<Player> {
+    let y = state.height_at(req.map.as_deref(), req.x, req.z).await;
     let mut players = state.players.write().await;
     let id = format!("player_{}", players.len() + 1);
     let player = Player {
         id: id.clone(),
         x: req.x,
         z: req.z,
-        y: None,
+        map: req.map,
+        y,
     };
     players.push(player.clone());
     println!("Created player {} at ({}, {})", id, req.x, req.z);
@@ -174,22 +314,42 @@ async fn move_player(
     State(state): State<Arc<AppState>>,
     Json(req): Json<MovePlayerRequest>,
 ) -> Json<String> {
-    let mut players = state.players.write().await;
+    let find_map = |players: &[Player]| {
+        players
+            .iter()
+            .find(|p| p.id == req.id)
+            .map(|p| p.map.clone())
+    };
+    let Some(mut map) = find_map(&state.players.read().await) else {
+        return Json(format!("Player {} not found", req.id));
+    };
 
-    if let Some(player) = players.iter_mut().find(|p| p.id == req.id) {
+    // Looked up before taking the write lock, so moves don't wait on the map
+    // catalog. Ids are reused after a clear, possibly on another map, in
+    // which case the height is looked up again.
+    loop {
+        let y = state.height_at(map.as_deref(), req.x, req.z).await;
+        let mut players = state.players.write().await;
+        let Some(player) = players.iter_mut().find(|p| p.id == req.id) else {
+            return Json(format!("Player {} not found", req.id));
+        };
+        if player.map != map {
+            map = player.map.clone();
+            continue;
+        }
         player.x = req.x;
         player.z = req.z;
+        player.y = y;
 
         // Broadcast the move
         let _ = state.tx.send(PlayerUpdate::Moved {
             id: req.id.clone(),
             x: req.x,
             z: req.z,
+            y,
         });
 
-        Json(format!("Player {} moved to ({}, {})", req.id, req.x, req.z))
-    } else {
-        Json(format!("Player {} not found", req.id))
+        return Json(format!("Player {} moved to ({}, {})", req.id, req.x, req.z));
     }
 }
 
@@ -224,14 +384,13 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     // Spawn task to send updates to this client
     let mut send_task = tokio::spawn(async move {
         while let Ok(msg) = rx.recv().await {
-            if let Ok(json) = serde_json::to_string(&msg) {
-                if sender
+            if let Ok(json) = serde_json::to_string(&msg)
+                && sender
                     .send(axum::extract::ws::Message::Text(json.into()))
                     .await
                     .is_err()
-                {
-                    break;
-                }
+            {
+                break;
             }
         }
     });
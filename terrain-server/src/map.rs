@@ -0,0 +1,321 @@
+use axum::body::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::{
+    assets::MapAssets, lod::LodPyramid, meta::MapMeta, mosaic::Mosaic, navmesh::NavMesh,
+    spatial::ChunkIndex,
+};
+
+/// A map file as written by the Scene2ThreeJs exporter.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MapFile {
+    pub scene: String,
+    pub terrains: Vec<TerrainChunk>,
+}
+
+/// One Unity terrain. `(x, y, z)` is the terrain's corner in world space and
+/// `heightMap` holds `resolution * resolution` heights in row-major order
+/// (rows along Z, columns along X), already scaled to `0..=maxHeight`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TerrainChunk {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub width: f32,
+    pub depth: f32,
+    pub max_height: f32,
+    pub resolution: u32,
+    pub height_map: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub enum ChunkError {
+    ResolutionTooSmall(u32),
+    HeightMapLength { expected: usize, actual: usize },
+    NonFiniteHeight { index: usize },
+    NonFiniteField(&'static str),
+    NonPositiveExtent { field: &'static str, value: f32 },
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::ResolutionTooSmall(res) => {
+                write!(f, "resolution {} is smaller than 2", res)
+            }
+            ChunkError::HeightMapLength { expected, actual } => write!(
+                f,
+                "heightMap has {} samples, expected resolution² = {}",
+                actual, expected
+            ),
+            ChunkError::NonFiniteHeight { index } => {
+                write!(f, "heightMap[{}] is not a finite number", index)
+            }
+            ChunkError::NonFiniteField(field) => write!(f, "{} is not a finite number", field),
+            ChunkError::NonPositiveExtent { field, value } => {
+                write!(f, "{} must be positive, got {}", field, value)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    NoTerrains,
+    Chunk {
+        index: usize,
+        name: String,
+        error: ChunkError,
+    },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "could not read file: {}", e),
+            MapError::Parse(e) => write!(f, "invalid JSON: {}", e),
+            MapError::NoTerrains => write!(f, "map has no terrains"),
+            MapError::Chunk { index, name, error } => {
+                write!(f, "terrain #{} ({}): {}", index, name, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl TerrainChunk {
+    pub fn validate(&self) -> Result<(), ChunkError> {
+        for (field, value) in [
+            ("x", self.x),
+            ("y", self.y),
+            ("z", self.z),
+            ("width", self.width),
+            ("depth", self.depth),
+            ("maxHeight", self.max_height),
+        ] {
+            if !value.is_finite() {
+                return Err(ChunkError::NonFiniteField(field));
+            }
+        }
+        for (field, value) in [
+            ("width", self.width),
+            ("depth", self.depth),
+            ("maxHeight", self.max_height),
+        ] {
+            if value <= 0.0 {
+                return Err(ChunkError::NonPositiveExtent { field, value });
+            }
+        }
+        if self.resolution < 2 {
+            return Err(ChunkError::ResolutionTooSmall(self.resolution));
+        }
+        let expected = self.resolution as usize * self.resolution as usize;
+        if self.height_map.len() != expected {
+            return Err(ChunkError::HeightMapLength {
+                expected,
+                actual: self.height_map.len(),
+            });
+        }
+        if let Some(index) = self.height_map.iter().position(|h| !h.is_finite()) {
+            return Err(ChunkError::NonFiniteHeight { index });
+        }
+        Ok(())
+    }
+}
+
+impl MapFile {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, MapError> {
+        let map: MapFile = serde_json::from_slice(bytes).map_err(MapError::Parse)?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// Index of the first chunk called `name`.
+    pub fn chunk_index(&self, name: &str) -> Option<usize> {
+        self.terrains.iter().position(|c| c.name == name)
+    }
+
+    pub fn validate(&self) -> Result<(), MapError> {
+        if self.terrains.is_empty() {
+            return Err(MapError::NoTerrains);
+        }
+        for (index, chunk) in self.terrains.iter().enumerate() {
+            chunk.validate().map_err(|error| MapError::Chunk {
+                index,
+                name: chunk.name.clone(),
+                error,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl TerrainChunk {
+    /// Square chunk at `(x, z)` with one world unit between samples, for
+    /// tests. `heights` are world heights in heightmap order.
+    pub fn from_heights(name: &str, x: f32, z: f32, heights: Vec<f32>) -> Self {
+        let resolution = (heights.len() as f64).sqrt() as u32;
+        assert_eq!((resolution * resolution) as usize, heights.len());
+        let side = (resolution - 1) as f32;
+        TerrainChunk {
+            name: name.to_string(),
+            x,
+            y: 0.0,
+            z,
+            width: side,
+            depth: side,
+            max_height: heights.iter().copied().fold(1.0, f32::max),
+            resolution,
+            height_map: heights,
+        }
+    }
+}
+
+/// A successfully parsed map together with the file it came from and the
+/// structures derived from it: the encoded variants served from `/maps`, a
+/// spatial index over its chunks, one LOD pyramid per chunk, the sidecar
+/// metadata if there is any and, once requested, the merged height raster
+/// and navmeshes for recently used agent profiles.
+pub struct LoadedMap {
+    pub file_name: String,
+    pub data: MapFile,
+    pub assets: MapAssets,
+    pub index: ChunkIndex,
+    pub lods: Vec<LodPyramid>,
+    pub meta: Option<MapMeta>,
+    pub mosaic: OnceLock<Result<Mosaic, String>>,
+    pub navmeshes: Mutex<Vec<Arc<NavMesh>>>,
+}
+
+impl LoadedMap {
+    pub fn load(path: &Path, file_name: String) -> Result<Self, MapError> {
+        let bytes = fs::read(path).map_err(MapError::Io)?;
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        let data = MapFile::from_slice(&bytes)?;
+        // Broken metadata shouldn't take the map down with it
+        let meta = MapMeta::load(path, &data).unwrap_or_else(|e| {
+            eprintln!("Ignoring metadata of {}: {}", file_name, e);
+            None
+        });
+        Ok(LoadedMap {
+            file_name,
+            index: ChunkIndex::new(&data),
+            lods: data.terrains.iter().map(LodPyramid::new).collect(),
+            meta,
+            mosaic: OnceLock::new(),
+            navmeshes: Mutex::new(Vec::new()),
+            data,
+            assets: MapAssets::new(Bytes::from(bytes), modified),
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct InvalidMap {
+    pub file: String,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub enum MapChange {
+    Added(String),
+    Updated(String),
+    Removed(String),
+}
+
+/// All maps found in `maps_dir`, keyed by file stem (`ps0_10_cave4`).
+#[derive(Default)]
+pub struct MapCatalog {
+    pub maps: BTreeMap<String, Arc<LoadedMap>>,
+    pub invalid: Vec<InvalidMap>,
+}
+
+impl MapCatalog {
+    pub fn load_dir(dir: &Path) -> Self {
+        let mut catalog = MapCatalog::default();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Error reading maps directory {:?}: {}", dir, e);
+                return catalog;
+            }
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let file_name = file_name.to_string();
+            catalog.apply(file_name.clone(), Some(LoadedMap::load(&path, file_name)));
+        }
+        catalog
+    }
+
+    /// Records the outcome of (re)loading `file_name`, or its deletion when
+    /// `loaded` is `None`, and reports how the set of valid maps changed.
+    pub fn apply(
+        &mut self,
+        file_name: String,
+        loaded: Option<Result<LoadedMap, MapError>>,
+    ) -> Option<MapChange> {
+        let key = map_key(&file_name).to_string();
+        let existed = self.maps.contains_key(&key);
+        self.invalid.retain(|m| m.file != file_name);
+
+        match loaded {
+            Some(Ok(map)) => {
+                self.maps.insert(key, Arc::new(map));
+                Some(if existed {
+                    MapChange::Updated(file_name)
+                } else {
+                    MapChange::Added(file_name)
+                })
+            }
+            Some(Err(e)) => {
+                eprintln!("Skipping invalid map {}: {}", file_name, e);
+                self.maps.remove(&key);
+                self.invalid.push(InvalidMap {
+                    file: file_name.clone(),
+                    reason: e.to_string(),
+                });
+                existed.then_some(MapChange::Removed(file_name))
+            }
+            None => {
+                self.maps.remove(&key);
+                existed.then_some(MapChange::Removed(file_name))
+            }
+        }
+    }
+
+    /// Looks a map up by scene name, with or without the `.json` extension.
+    pub fn get(&self, name: &str) -> Option<Arc<LoadedMap>> {
+        self.maps.get(map_key(name)).cloned()
+    }
+}
+
+pub fn map_key(name: &str) -> &str {
+    name.strip_suffix(".json").unwrap_or(name)
+}
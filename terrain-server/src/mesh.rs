@@ -0,0 +1,107 @@
+use crate::map::TerrainChunk;
+
+/// Indexed triangle mesh with per-vertex normals.
+pub struct TriangleMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl TriangleMesh {
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        self.positions.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(min, max), p| {
+                (
+                    [0, 1, 2].map(|i| min[i].min(p[i])),
+                    [0, 1, 2].map(|i| max[i].max(p[i])),
+                )
+            },
+        )
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+    }
+}
+
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len > 0.0 {
+        v.map(|c| c / len)
+    } else {
+        [0.0, 1.0, 0.0]
+    }
+}
+
+impl TerrainChunk {
+    /// World-space position of the chunk's centre at its base height, which is
+    /// where the viewer places each chunk's mesh.
+    pub fn center(&self) -> [f32; 3] {
+        [self.x + self.width / 2.0, self.y, self.z + self.depth / 2.0]
+    }
+
+    /// Triangulates the heightmap the same way the viewer's `PlaneGeometry`
+    /// does once rotated onto the XZ plane: vertices are relative to
+    /// `center()`, X grows with the column, Z with the row, and triangles wind
+    /// counter-clockwise seen from above. Heights are multiplied by
+    /// `vertical_scale`.
+    pub fn mesh(&self, vertical_scale: f32) -> TriangleMesh {
+        let res = self.resolution as usize;
+        let step_x = self.width / (res - 1) as f32;
+        let step_z = self.depth / (res - 1) as f32;
+
+        let mut positions = Vec::with_capacity(res * res);
+        for row in 0..res {
+            for col in 0..res {
+                positions.push([
+                    col as f32 * step_x - self.width / 2.0,
+                    self.sample(col, row) * vertical_scale,
+                    row as f32 * step_z - self.depth / 2.0,
+                ]);
+            }
+        }
+
+        // Central differences, one-sided at the border
+        let mut normals = Vec::with_capacity(res * res);
+        for row in 0..res {
+            for col in 0..res {
+                let (c0, c1) = (col.saturating_sub(1), (col + 1).min(res - 1));
+                let (r0, r1) = (row.saturating_sub(1), (row + 1).min(res - 1));
+                let dx = (self.sample(c1, row) - self.sample(c0, row)) * vertical_scale
+                    / ((c1 - c0) as f32 * step_x);
+                let dz = (self.sample(col, r1) - self.sample(col, r0)) * vertical_scale
+                    / ((r1 - r0) as f32 * step_z);
+                normals.push(normalize([-dx, 1.0, -dz]));
+            }
+        }
+
+        let mut indices = Vec::with_capacity((res - 1) * (res - 1) * 6);
+        for row in 0..res - 1 {
+            for col in 0..res - 1 {
+                let a = (row * res + col) as u32;
+                let b = a + res as u32;
+                indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+            }
+        }
+
+        TriangleMesh {
+            positions,
+            normals,
+            indices,
+        }
+    }
+
+    /// Like `mesh`, but in world coordinates. The exaggeration applies to
+    /// world heights, so neighbouring chunks with different `y` stay aligned.
+    pub fn world_mesh(&self, vertical_scale: f32) -> TriangleMesh {
+        let mut mesh = self.mesh(vertical_scale);
+        let [cx, cy, cz] = self.center();
+        for p in &mut mesh.positions {
+            p[0] += cx;
+            p[1] += cy * vertical_scale;
+            p[2] += cz;
+        }
+        mesh
+    }
+}
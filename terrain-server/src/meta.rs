@@ -0,0 +1,83 @@
+//! Optional `<map>.meta.toml` sidecars with data the exporter doesn't know
+//! about: a display name, tags, a description, the water level, a default
+//! camera pose and named spawn points. See `map.meta.example.toml`.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::map::MapFile;
+
+const META_SUFFIX: &str = ".meta.toml";
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(rename_all(serialize = "camelCase"), deny_unknown_fields)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(rename_all(serialize = "camelCase"), deny_unknown_fields)]
+pub struct SpawnPoint {
+    pub name: String,
+    pub x: f32,
+    pub z: f32,
+    /// Sampled from the terrain when left out.
+    pub y: Option<f32>,
+    /// Facing, in degrees clockwise from +Z.
+    #[serde(default)]
+    pub heading: f32,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(rename_all(serialize = "camelCase"), deny_unknown_fields)]
+pub struct MapMeta {
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub water_level: Option<f32>,
+    pub camera: Option<CameraPose>,
+    #[serde(default)]
+    pub spawn_points: Vec<SpawnPoint>,
+}
+
+/// `maps/ps0_10_cave4.json` → `maps/ps0_10_cave4.meta.toml`.
+pub fn meta_path(map_path: &Path) -> PathBuf {
+    let stem = map_path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    map_path.with_file_name(format!("{}{}", stem, META_SUFFIX))
+}
+
+/// The map a sidecar belongs to, or `None` if `path` isn't a sidecar.
+pub fn map_path(meta_path: &Path) -> Option<PathBuf> {
+    let name = meta_path.file_name()?.to_str()?;
+    let stem = name.strip_suffix(META_SUFFIX)?;
+    Some(meta_path.with_file_name(format!("{}.json", stem)))
+}
+
+impl MapMeta {
+    /// Reads the sidecar of the map at `map_path`, if there is one. Spawn
+    /// points without a height are placed on the terrain of `map`.
+    pub fn load(map_path: &Path, map: &MapFile) -> Result<Option<MapMeta>, String> {
+        let path = meta_path(map_path);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("could not read {:?}: {}", path, e)),
+        };
+        let mut meta: MapMeta =
+            toml::from_str(&text).map_err(|e| format!("invalid {:?}: {}", path, e))?;
+        for spawn in &mut meta.spawn_points {
+            if spawn.y.is_none() {
+                spawn.y = map.height_at(spawn.x, spawn.z);
+            }
+        }
+        Ok(Some(meta))
+    }
+}
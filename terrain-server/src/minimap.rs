@@ -0,0 +1,238 @@
+//! Top-down minimaps rendered from the mosaic: a height colour ramp shaded by
+//! a hillshade lit from the top left, with transparency where there is no
+//! terrain. The image's top left is the map's minimum X and Z.
+//!
+//! Rendered images are cached on disk under `cache_dir/minimaps`, keyed by the
+//! map's content hash and the image size, so a re-exported map is rendered
+//! again and the stale files are removed.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::{fs, path::PathBuf, sync::Arc};
+
+use crate::{
+    AppState,
+    api::ApiError,
+    assets,
+    map::{LoadedMap, map_key},
+    mosaic::Mosaic,
+    validate::write_atomically,
+};
+
+const DEFAULT_SIZE: u32 = 256;
+const MIN_SIZE: u32 = 16;
+const MAX_SIZE: u32 = 2048;
+
+/// Size of the thumbnails linked from the map list.
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+/// Share of a colour kept on faces turned away from the light.
+const AMBIENT: f32 = 0.35;
+
+/// Colour ramp from the lowest to the highest point of a map.
+const RAMP: [(f32, [f32; 3]); 5] = [
+    (0.0, [0.15, 0.30, 0.35]),
+    (0.25, [0.27, 0.48, 0.25]),
+    (0.5, [0.66, 0.62, 0.36]),
+    (0.75, [0.54, 0.42, 0.30]),
+    (1.0, [0.94, 0.94, 0.92]),
+];
+
+fn ramp(t: f32) -> [f32; 3] {
+    let t = t.clamp(0.0, 1.0);
+    for pair in RAMP.windows(2) {
+        let (t0, c0) = pair[0];
+        let (t1, c1) = pair[1];
+        if t <= t1 {
+            let f = (t - t0) / (t1 - t0);
+            return [0, 1, 2].map(|i| c0[i] + (c1[i] - c0[i]) * f);
+        }
+    }
+    RAMP[RAMP.len() - 1].1
+}
+
+/// Renders the mosaic into RGBA pixels, `size` pixels along its longer side.
+/// Returns the image's width, height and pixels.
+fn render(mosaic: &Mosaic, size: u32) -> (u32, u32, Vec<u8>) {
+    let extent_x = mosaic.max_x() - mosaic.min_x;
+    let extent_z = mosaic.max_z() - mosaic.min_z;
+    let (width, height) = if extent_x >= extent_z {
+        let h = (size as f32 * extent_z / extent_x).round() as u32;
+        (size, h.max(1))
+    } else {
+        let w = (size as f32 * extent_x / extent_z).round() as u32;
+        (w.max(1), size)
+    };
+    let pixel = (extent_x / width as f32).max(extent_z / height as f32);
+
+    let mut heights = Vec::with_capacity((width * height) as usize);
+    for py in 0..height {
+        let z = mosaic.min_z + (py as f32 + 0.5) / height as f32 * extent_z;
+        for px in 0..width {
+            let x = mosaic.min_x + (px as f32 + 0.5) / width as f32 * extent_x;
+            heights.push(mosaic.sample(x, z));
+        }
+    }
+    let (lo, hi) = heights
+        .iter()
+        .flatten()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &h| {
+            (lo.min(h), hi.max(h))
+        });
+    let range = (hi - lo).max(f32::EPSILON);
+
+    // Light from the top left of the image, 45° above the horizon
+    let light = [-0.5, std::f32::consts::FRAC_1_SQRT_2, -0.5];
+
+    let at = |px: i64, py: i64| -> Option<f32> {
+        if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+            return None;
+        }
+        heights[(py * width as i64 + px) as usize]
+    };
+
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for py in 0..height as i64 {
+        for px in 0..width as i64 {
+            let Some(h) = at(px, py) else {
+                pixels.extend_from_slice(&[0, 0, 0, 0]);
+                continue;
+            };
+            // Central differences, one-sided next to edges and holes
+            let slope = |before: Option<f32>, after: Option<f32>| match (before, after) {
+                (Some(b), Some(a)) => (a - b) / (2.0 * pixel),
+                (Some(b), None) => (h - b) / pixel,
+                (None, Some(a)) => (a - h) / pixel,
+                (None, None) => 0.0,
+            };
+            let dx = slope(at(px - 1, py), at(px + 1, py));
+            let dz = slope(at(px, py - 1), at(px, py + 1));
+            let normal = [-dx, 1.0, -dz];
+            let length = (dx * dx + 1.0 + dz * dz).sqrt();
+            let shade = ((0..3).map(|i| normal[i] * light[i]).sum::<f32>() / length).max(0.0);
+
+            let colour = ramp((h - lo) / range);
+            let lit = AMBIENT + (1.0 - AMBIENT) * shade;
+            for c in colour {
+                pixels.push(((c * lit).clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+            pixels.push(255);
+        }
+    }
+    (width, height, pixels)
+}
+
+fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Encoding into memory cannot fail
+    let mut writer = encoder
+        .write_header()
+        .expect("in-memory PNG encoding failed");
+    writer
+        .write_image_data(pixels)
+        .expect("in-memory PNG encoding failed");
+    writer.finish().expect("in-memory PNG encoding failed");
+    out
+}
+
+/// `{map}-{hash}-{size}.png`; the map key itself may contain dashes.
+fn cache_file_name(key: &str, hash: u64, size: u32) -> String {
+    format!("{}-{:016x}-{}.png", key, hash, size)
+}
+
+/// Removes cached minimaps of `key` rendered from other versions of the map.
+fn remove_stale(dir: &std::path::Path, key: &str, hash: u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let current = format!("{:016x}", hash);
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(stem) = name.to_str().and_then(|n| n.strip_suffix(".png")) else {
+            continue;
+        };
+        let mut parts = stem.rsplitn(3, '-');
+        let (_size, file_hash, file_key) = (parts.next(), parts.next(), parts.next());
+        if file_key == Some(key) && file_hash != Some(current.as_str()) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Returns the PNG for `map` at `size`, rendering and caching it if needed.
+fn minimap_png(map: &LoadedMap, cache_dir: PathBuf, size: u32) -> Result<Vec<u8>, ApiError> {
+    let key = map_key(&map.file_name);
+    let hash = map.assets.content_hash();
+    let dir = cache_dir.join("minimaps");
+    let path = dir.join(cache_file_name(key, hash, size));
+    if let Ok(png) = fs::read(&path) {
+        return Ok(png);
+    }
+
+    let (width, height, pixels) = render(map.mosaic()?, size);
+    let png = encode_png(width, height, &pixels);
+
+    // A failed cache write only costs a re-render next time
+    if let Err(e) = fs::create_dir_all(&dir).and_then(|_| write_atomically(&path, &png)) {
+        eprintln!("Could not cache minimap {:?}: {}", path, e);
+    } else {
+        remove_stale(&dir, key, hash);
+    }
+    Ok(png)
+}
+
+/// URL of a map's thumbnail, as returned by the map list.
+pub fn thumbnail_url(map: &LoadedMap) -> String {
+    format!(
+        "/api/maps/{}/minimap.png?size={}",
+        map_key(&map.file_name),
+        THUMBNAIL_SIZE
+    )
+}
+
+#[derive(Deserialize)]
+pub struct MinimapQuery {
+    /// Pixels along the longer side of the image.
+    size: Option<u32>,
+}
+
+pub async fn get_minimap(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<MinimapQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let size = query.size.unwrap_or(DEFAULT_SIZE);
+    if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+        return Err(ApiError::BadRequest(format!(
+            "size must be between {} and {}",
+            MIN_SIZE, MAX_SIZE
+        )));
+    }
+
+    let map = state.map(&name).await?;
+    let etag = format!("\"{:016x}-minimap-{}\"", map.assets.content_hash(), size);
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, value);
+    }
+    if assets::not_modified(&headers, &etag, map.assets.modified()) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    let cache_dir = state.cache_dir.clone();
+    let png = match tokio::task::spawn_blocking(move || minimap_png(&map, cache_dir, size)).await {
+        Ok(png) => png?,
+        Err(_) => return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    };
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+    Ok((response_headers, png).into_response())
+}
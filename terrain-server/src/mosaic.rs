@@ -0,0 +1,376 @@
+//! All of a map's chunks merged into one height raster.
+//!
+//! The mosaic covers the bounding rectangle of every chunk with a regular
+//! grid at the spacing of the finest chunk. Grid nodes that no chunk covers
+//! hold no data. It is built the first time it is needed and kept for the
+//! lifetime of the `LoadedMap`.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{LoadedMap, MapFile},
+};
+
+/// Upper bound on mosaic nodes; maps that would exceed it get a coarser grid.
+const MAX_MOSAIC_CELLS: usize = 16 * 1024 * 1024;
+
+/// Upper bound on the nodes in one region response.
+const MAX_REGION_CELLS: usize = 4 * 1024 * 1024;
+
+/// Slack, in grid cells, when deciding whether a node lies on a chunk's edge.
+const NODE_EPSILON: f32 = 1e-3;
+
+/// Regular grid of world heights. Node `(col, row)` sits at
+/// `(min_x + col * cell_size, min_z + row * cell_size)`; rows follow Z and
+/// `heights` is row-major, like a chunk's heightmap. No-data is `NaN`.
+pub struct Mosaic {
+    pub min_x: f32,
+    pub min_z: f32,
+    pub cell_size: f32,
+    pub cols: usize,
+    pub rows: usize,
+    heights: Vec<f32>,
+}
+
+impl Mosaic {
+    /// Fails for maps whose chunks lie so far apart that a grid within the
+    /// node limit would be coarser than their smallest chunk.
+    pub fn new(map: &MapFile) -> Result<Self, String> {
+        let min_x = map
+            .terrains
+            .iter()
+            .map(|c| c.x)
+            .fold(f32::INFINITY, f32::min);
+        let min_z = map
+            .terrains
+            .iter()
+            .map(|c| c.z)
+            .fold(f32::INFINITY, f32::min);
+        let max_x = map
+            .terrains
+            .iter()
+            .map(|c| c.x + c.width)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let max_z = map
+            .terrains
+            .iter()
+            .map(|c| c.z + c.depth)
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        let mut cell_size = map
+            .terrains
+            .iter()
+            .map(|c| c.width.min(c.depth) / (c.resolution - 1) as f32)
+            .fold(f32::INFINITY, f32::min);
+        let smallest_side = map
+            .terrains
+            .iter()
+            .map(|c| c.width.min(c.depth))
+            .fold(f32::INFINITY, f32::min);
+        // In f64, as the node counts of far-flung chunks overflow a usize
+        let nodes = |cell: f32| {
+            let cols = ((max_x as f64 - min_x as f64) / cell as f64).round() + 1.0;
+            let rows = ((max_z as f64 - min_z as f64) / cell as f64).round() + 1.0;
+            (cols, rows)
+        };
+        while {
+            let (cols, rows) = nodes(cell_size);
+            cols * rows > MAX_MOSAIC_CELLS as f64
+        } {
+            cell_size *= 2.0;
+            if cell_size > smallest_side {
+                return Err(format!(
+                    "chunks of {} span {}×{} world units, too far apart for a mosaic",
+                    map.scene,
+                    max_x as f64 - min_x as f64,
+                    max_z as f64 - min_z as f64
+                ));
+            }
+        }
+        let (cols, rows) = nodes(cell_size);
+        let (cols, rows) = (cols as usize, rows as usize);
+
+        let mut heights = vec![f32::NAN; cols * rows];
+        // Where chunks overlap the first one in the file wins, as in
+        // `MapFile::chunk_at`
+        for chunk in &map.terrains {
+            let first = |min: f32, origin: f32| {
+                (((min - origin) / cell_size - NODE_EPSILON).ceil()).max(0.0) as usize
+            };
+            let last = |max: f32, origin: f32, count: usize| {
+                (((max - origin) / cell_size + NODE_EPSILON).floor() as usize).min(count - 1)
+            };
+            let (col0, col1) = (
+                first(chunk.x, min_x),
+                last(chunk.x + chunk.width, min_x, cols),
+            );
+            let (row0, row1) = (
+                first(chunk.z, min_z),
+                last(chunk.z + chunk.depth, min_z, rows),
+            );
+            for row in row0..=row1 {
+                let z = min_z + row as f32 * cell_size;
+                for col in col0..=col1 {
+                    let h = &mut heights[row * cols + col];
+                    if h.is_nan() {
+                        *h = chunk.height_at(min_x + col as f32 * cell_size, z);
+                    }
+                }
+            }
+        }
+
+        Ok(Mosaic {
+            min_x,
+            min_z,
+            cell_size,
+            cols,
+            rows,
+            heights,
+        })
+    }
+
+    pub fn max_x(&self) -> f32 {
+        self.min_x + (self.cols - 1) as f32 * self.cell_size
+    }
+
+    pub fn max_z(&self) -> f32 {
+        self.min_z + (self.rows - 1) as f32 * self.cell_size
+    }
+
+    /// Height at a grid node, or `None` for no-data and nodes off the grid.
+    pub fn get(&self, col: usize, row: usize) -> Option<f32> {
+        if col >= self.cols || row >= self.rows {
+            return None;
+        }
+        let h = self.heights[row * self.cols + col];
+        (!h.is_nan()).then_some(h)
+    }
+
+    /// World height at `(x, z)`. Interpolates bilinearly when all four
+    /// surrounding nodes have data and falls back to the nearest node next to
+    /// no-data, so holes don't bleed into their surroundings.
+    pub fn sample(&self, x: f32, z: f32) -> Option<f32> {
+        let u = (x - self.min_x) / self.cell_size;
+        let v = (z - self.min_z) / self.cell_size;
+        let last_col = (self.cols - 1) as f32;
+        let last_row = (self.rows - 1) as f32;
+        if !(u >= -NODE_EPSILON
+            && v >= -NODE_EPSILON
+            && u <= last_col + NODE_EPSILON
+            && v <= last_row + NODE_EPSILON)
+        {
+            return None;
+        }
+        let u = u.clamp(0.0, last_col);
+        let v = v.clamp(0.0, last_row);
+
+        let col0 = u.floor() as usize;
+        let row0 = v.floor() as usize;
+        let col1 = (col0 + 1).min(self.cols - 1);
+        let row1 = (row0 + 1).min(self.rows - 1);
+        let fu = u - col0 as f32;
+        let fv = v - row0 as f32;
+
+        match (
+            self.get(col0, row0),
+            self.get(col1, row0),
+            self.get(col0, row1),
+            self.get(col1, row1),
+        ) {
+            (Some(h00), Some(h10), Some(h01), Some(h11)) => {
+                let top = h00 * (1.0 - fu) + h10 * fu;
+                let bottom = h01 * (1.0 - fu) + h11 * fu;
+                Some(top * (1.0 - fv) + bottom * fv)
+            }
+            _ => self.get(u.round() as usize, v.round() as usize),
+        }
+    }
+}
+
+impl LoadedMap {
+    /// The map's mosaic, built on first use; fails the same way every time
+    /// for maps that can't have one.
+    pub fn mosaic(&self) -> Result<&Mosaic, ApiError> {
+        self.mosaic
+            .get_or_init(|| Mosaic::new(&self.data))
+            .as_ref()
+            .map_err(|e| ApiError::BadRequest(e.clone()))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RegionQuery {
+    /// `minX,minZ,maxX,maxZ`; the whole mosaic by default.
+    bbox: Option<String>,
+    /// Output grid spacing in world units; the mosaic's own by default.
+    res: Option<f32>,
+}
+
+/// A resampled crop of the mosaic. Same layout as `Mosaic`; `null` heights
+/// are no-data.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    scene: String,
+    min_x: f32,
+    min_z: f32,
+    max_x: f32,
+    max_z: f32,
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+    heights: Vec<Option<f32>>,
+}
+
+fn parse_bbox(bbox: &str) -> Result<[f32; 4], ApiError> {
+    let invalid = || ApiError::BadRequest("bbox must be minX,minZ,maxX,maxZ".to_string());
+    let values: Vec<f32> = bbox
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let [min_x, min_z, max_x, max_z] = values[..] else {
+        return Err(invalid());
+    };
+    if !values.iter().all(|v| v.is_finite()) || min_x > max_x || min_z > max_z {
+        return Err(invalid());
+    }
+    Ok([min_x, min_z, max_x, max_z])
+}
+
+fn region(
+    map: &LoadedMap,
+    bbox: Option<[f32; 4]>,
+    res: Option<f32>,
+) -> Result<Json<Region>, ApiError> {
+    let mosaic = map.mosaic()?;
+    let [min_x, min_z, max_x, max_z] =
+        bbox.unwrap_or([mosaic.min_x, mosaic.min_z, mosaic.max_x(), mosaic.max_z()]);
+    let cell_size = res.unwrap_or(mosaic.cell_size);
+
+    // Checked in f64 before converting, as huge boxes overflow a usize
+    let count = |min: f32, max: f32| {
+        ((max as f64 - min as f64) / cell_size as f64 + NODE_EPSILON as f64).floor() + 1.0
+    };
+    let (cols, rows) = (count(min_x, max_x), count(min_z, max_z));
+    if cols * rows > MAX_REGION_CELLS as f64 {
+        return Err(ApiError::BadRequest(format!(
+            "region would have {}×{} samples, more than the limit of {}; use a larger res",
+            cols, rows, MAX_REGION_CELLS
+        )));
+    }
+    let (cols, rows) = (cols as usize, rows as usize);
+
+    let mut heights = Vec::with_capacity(cols * rows);
+    for row in 0..rows {
+        let z = min_z + row as f32 * cell_size;
+        for col in 0..cols {
+            heights.push(mosaic.sample(min_x + col as f32 * cell_size, z));
+        }
+    }
+
+    Ok(Json(Region {
+        scene: map.data.scene.clone(),
+        min_x,
+        min_z,
+        max_x: min_x + (cols - 1) as f32 * cell_size,
+        max_z: min_z + (rows - 1) as f32 * cell_size,
+        cell_size,
+        cols,
+        rows,
+        heights,
+    }))
+}
+
+pub async fn get_region(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<RegionQuery>,
+) -> Result<Json<Region>, ApiError> {
+    let bbox = query.bbox.as_deref().map(parse_bbox).transpose()?;
+    if let Some(res) = query.res
+        && !(res.is_finite() && res > 0.0)
+    {
+        return Err(ApiError::BadRequest(
+            "res must be a positive number".to_string(),
+        ));
+    }
+
+    let map = state.map(&name).await?;
+    tokio::task::spawn_blocking(move || region(&map, bbox, query.res))
+        .await
+        .map_err(|_| ApiError::Internal("Region task failed".to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TerrainChunk;
+
+    fn map(terrains: Vec<TerrainChunk>) -> MapFile {
+        MapFile {
+            scene: "test".to_string(),
+            terrains,
+        }
+    }
+
+    #[test]
+    fn overlaps_go_to_the_first_chunk_and_gaps_have_no_data() {
+        // `b` overlaps `a` from x = 1 to 2; `c` leaves a gap at x = 4
+        let mosaic = Mosaic::new(&map(vec![
+            TerrainChunk::from_heights("a", 0.0, 0.0, vec![1.0; 9]),
+            TerrainChunk::from_heights("b", 1.0, 0.0, vec![5.0; 9]),
+            TerrainChunk::from_heights("c", 5.0, 0.0, vec![7.0; 9]),
+        ]))
+        .unwrap();
+        assert_eq!((mosaic.cols, mosaic.rows, mosaic.cell_size), (8, 3, 1.0));
+
+        let row: Vec<Option<f32>> = (0..mosaic.cols).map(|col| mosaic.get(col, 1)).collect();
+        assert_eq!(
+            row,
+            [
+                Some(1.0),
+                Some(1.0),
+                Some(1.0),
+                Some(5.0),
+                None,
+                Some(7.0),
+                Some(7.0),
+                Some(7.0)
+            ]
+        );
+        assert_eq!(mosaic.get(0, 3), None);
+    }
+
+    #[test]
+    fn sampling_next_to_no_data_uses_the_nearest_node() {
+        // Nodes at x = 2 have no data
+        let mosaic = Mosaic::new(&map(vec![
+            TerrainChunk::from_heights("a", 0.0, 0.0, vec![0.0, 2.0, 0.0, 2.0]),
+            TerrainChunk::from_heights("b", 3.0, 0.0, vec![6.0; 4]),
+        ]))
+        .unwrap();
+
+        assert_eq!(mosaic.sample(0.25, 0.5), Some(0.5));
+        assert_eq!(mosaic.sample(1.25, 0.5), Some(2.0));
+        assert_eq!(mosaic.sample(2.75, 0.5), Some(6.0));
+        assert_eq!(mosaic.sample(2.0, 0.5), None);
+        assert_eq!(mosaic.sample(-0.5, 0.5), None);
+    }
+
+    #[test]
+    fn far_flung_chunks_are_rejected() {
+        let result = Mosaic::new(&map(vec![
+            TerrainChunk::from_heights("a", 0.0, 0.0, vec![0.0; 4]),
+            TerrainChunk::from_heights("b", 1e30, 0.0, vec![0.0; 4]),
+        ]));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,949 @@
+//! Walkable navigation meshes built from a map's mosaic.
+//!
+//! A mosaic cell is walkable when every edge of its two triangles passes the
+//! agent's slope and step limits and, if the agent has a `maxWadeDepth` and
+//! the map a `water_level`, the water over it is no deeper than that.
+//! Heightfields have no overhangs, so the agent height never limits where it
+//! can go. The walkable cells are shrunk by the agent radius and covered with
+//! rectangles of nearly planar terrain, which become the mesh's polygons.
+//!
+//! Paths are searched over the polygons and straightened with the funnel
+//! algorithm, so unlike grid paths they aren't bound to eight directions.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    AppState,
+    api::ApiError,
+    export,
+    map::LoadedMap,
+    mesh::{TriangleMesh, normalize},
+    mosaic::Mosaic,
+    pathfind::WalkProfile,
+};
+
+/// How far, in world units, a polygon may stray from the terrain under it.
+const PLANE_TOLERANCE: f32 = 0.1;
+
+/// Longest polygon side, in cells.
+const MAX_POLYGON_CELLS: usize = 64;
+
+/// Navmeshes kept per map, for different agent profiles.
+const NAVMESH_CACHE_SIZE: usize = 4;
+
+const NO_POLYGON: u32 = u32::MAX;
+
+/// The agent a navmesh is built for. The defaults are Unity's NavMesh agent
+/// defaults.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentProfile {
+    /// Distance the agent keeps from obstacles and the map's edge.
+    #[serde(default = "default_agent_radius")]
+    pub agent_radius: f32,
+    /// Headroom the agent needs. Heightfields have no overhangs, so every
+    /// cell has it; kept so profiles match Unity's agent settings.
+    #[serde(default = "default_agent_height")]
+    pub agent_height: f32,
+    /// Deepest water the agent can wade through; water doesn't stop it if
+    /// unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_wade_depth: Option<f32>,
+    /// Steepest walkable slope, in degrees.
+    #[serde(default = "default_max_slope")]
+    pub max_slope: f32,
+    /// Highest rise the agent can step up or down regardless of slope.
+    #[serde(default = "default_step_height")]
+    pub step_height: f32,
+}
+
+fn default_agent_radius() -> f32 {
+    0.5
+}
+
+fn default_agent_height() -> f32 {
+    2.0
+}
+
+fn default_max_slope() -> f32 {
+    45.0
+}
+
+fn default_step_height() -> f32 {
+    0.4
+}
+
+impl AgentProfile {
+    pub fn walk(&self) -> WalkProfile {
+        WalkProfile {
+            max_slope: self.max_slope,
+            step_height: self.step_height,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ApiError> {
+        self.walk().validate()?;
+        if !(self.agent_radius.is_finite() && self.agent_radius >= 0.0) {
+            return Err(ApiError::BadRequest(
+                "agentRadius must be a non-negative number".to_string(),
+            ));
+        }
+        if !(self.agent_height.is_finite() && self.agent_height > 0.0) {
+            return Err(ApiError::BadRequest(
+                "agentHeight must be a positive number".to_string(),
+            ));
+        }
+        if let Some(depth) = self.max_wade_depth
+            && !(depth.is_finite() && depth >= 0.0)
+        {
+            return Err(ApiError::BadRequest(
+                "maxWadeDepth must be a non-negative number".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Edge shared by two polygons, from `a` to `b` as `[x, z]`.
+pub struct Portal {
+    pub to: usize,
+    pub a: [f32; 2],
+    pub b: [f32; 2],
+}
+
+/// Rectangle of mosaic cells starting at cell `(col, row)`.
+pub struct Polygon {
+    pub col: usize,
+    pub row: usize,
+    pub cols: usize,
+    pub rows: usize,
+    pub portals: Vec<Portal>,
+}
+
+pub struct NavMesh {
+    pub agent: AgentProfile,
+    min_x: f32,
+    min_z: f32,
+    cell_size: f32,
+    /// Cells, one fewer than the mosaic has nodes in each direction.
+    cols: usize,
+    rows: usize,
+    /// Polygon covering each cell, or `NO_POLYGON`.
+    cell_polygons: Vec<u32>,
+    pub polygons: Vec<Polygon>,
+    /// The polygons as triangles, in world coordinates.
+    pub mesh: TriangleMesh,
+    pub walkable_area: f32,
+    /// Area of the cells the mosaic has data for.
+    pub terrain_area: f32,
+}
+
+/// Squared distance transform of one line (Felzenszwalb & Huttenlocher):
+/// `out[q]` becomes the minimum of `(q - p)² + f[p]` over all `p`.
+fn distance_transform_1d(f: &[f64], out: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+    let n = f.len();
+    let intersect = |q: usize, p: usize| {
+        let (qf, pf) = (q as f64, p as f64);
+        ((f[q] + qf * qf) - (f[p] + pf * pf)) / (2.0 * (qf - pf))
+    };
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+    for q in 1..n {
+        let mut s = intersect(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersect(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+    k = 0;
+    for (q, out) in out.iter_mut().enumerate() {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let d = q as f64 - v[k] as f64;
+        *out = d * d + f[v[k]];
+    }
+}
+
+/// Clears walkable cells closer than `radius` cells to an unwalkable one or
+/// to the grid's edge, measured between cell edges.
+fn erode(walkable: &mut [bool], cols: usize, rows: usize, radius: f32) {
+    // Pad with a ring of blocked cells so the grid's edge counts as blocked
+    let (width, height) = (cols + 2, rows + 2);
+    let far = ((width * width + height * height) as f64) * 4.0;
+    let mut dist = vec![0.0; width * height];
+    for row in 0..rows {
+        for col in 0..cols {
+            if walkable[row * cols + col] {
+                dist[(row + 1) * width + col + 1] = far;
+            }
+        }
+    }
+
+    let longest = width.max(height);
+    let (mut line, mut out) = (vec![0.0; longest], vec![0.0; longest]);
+    let (mut v, mut z) = (vec![0; longest], vec![0.0; longest + 1]);
+    for col in 0..width {
+        for row in 0..height {
+            line[row] = dist[row * width + col];
+        }
+        distance_transform_1d(&line[..height], &mut out[..height], &mut v, &mut z);
+        for row in 0..height {
+            dist[row * width + col] = out[row];
+        }
+    }
+    for row in 0..height {
+        let cells = row * width..(row + 1) * width;
+        line[..width].copy_from_slice(&dist[cells.clone()]);
+        distance_transform_1d(&line[..width], &mut out[..width], &mut v, &mut z);
+        dist[cells].copy_from_slice(&out[..width]);
+    }
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let clearance = dist[(row + 1) * width + col + 1].sqrt() as f32 - 0.5;
+            if clearance < radius {
+                walkable[row * cols + col] = false;
+            }
+        }
+    }
+}
+
+/// Height of the two-triangle surface spanned by a rectangle's corner
+/// heights at fraction `(u, v)` of it. The triangles split along the same
+/// diagonal as chunk meshes.
+fn rectangle_height([h00, h10, h01, h11]: [f32; 4], u: f32, v: f32) -> f32 {
+    if u + v <= 1.0 {
+        h00 + u * (h10 - h00) + v * (h01 - h00)
+    } else {
+        h11 + (1.0 - u) * (h01 - h11) + (1.0 - v) * (h10 - h11)
+    }
+}
+
+/// Whether the `cols`×`rows` cells at `(col, row)` are flat enough for one
+/// polygon.
+fn is_planar(mosaic: &Mosaic, col: usize, row: usize, cols: usize, rows: usize) -> bool {
+    let height = |c: usize, r: usize| mosaic.get(c, r).unwrap_or(f32::NAN);
+    let corners = [
+        height(col, row),
+        height(col + cols, row),
+        height(col, row + rows),
+        height(col + cols, row + rows),
+    ];
+    (0..=rows).all(|j| {
+        (0..=cols).all(|i| {
+            let expected =
+                rectangle_height(corners, i as f32 / cols as f32, j as f32 / rows as f32);
+            (height(col + i, row + j) - expected).abs() <= PLANE_TOLERANCE
+        })
+    })
+}
+
+fn cross([ax, az]: [f32; 2], [bx, bz]: [f32; 2]) -> f32 {
+    ax * bz - az * bx
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+fn same_point(a: [f32; 2], b: [f32; 2]) -> bool {
+    distance(a, b) < 1e-6
+}
+
+/// Where on `portal` a walk from `entry` towards `goal` would cross it:
+/// the point minimising the distance via it. That distance is convex along
+/// the portal, so a ternary search finds it.
+fn crossing(entry: [f32; 2], goal: [f32; 2], portal: &Portal) -> [f32; 2] {
+    let at = |t: f32| {
+        [
+            portal.a[0] + (portal.b[0] - portal.a[0]) * t,
+            portal.a[1] + (portal.b[1] - portal.a[1]) * t,
+        ]
+    };
+    let via = |t: f32| distance(entry, at(t)) + distance(at(t), goal);
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..24 {
+        let (m1, m2) = (lo + (hi - lo) / 3.0, hi - (hi - lo) / 3.0);
+        if via(m1) <= via(m2) {
+            hi = m2;
+        } else {
+            lo = m1;
+        }
+    }
+    at((lo + hi) / 2.0)
+}
+
+impl NavMesh {
+    pub fn new(mosaic: &Mosaic, water_level: Option<f32>, agent: AgentProfile) -> Self {
+        let cols = mosaic.cols - 1;
+        let rows = mosaic.rows - 1;
+        let cell = mosaic.cell_size;
+        let walk = agent.walk();
+
+        let mut terrain_area = 0.0;
+        let mut walkable = vec![false; cols * rows];
+        for row in 0..rows {
+            for col in 0..cols {
+                let (Some(h00), Some(h10), Some(h01), Some(h11)) = (
+                    mosaic.get(col, row),
+                    mosaic.get(col + 1, row),
+                    mosaic.get(col, row + 1),
+                    mosaic.get(col + 1, row + 1),
+                ) else {
+                    continue;
+                };
+                terrain_area += cell * cell;
+                let diagonal = cell * std::f32::consts::SQRT_2;
+                let passable = [
+                    (h00, h10, cell),
+                    (h01, h11, cell),
+                    (h00, h01, cell),
+                    (h10, h11, cell),
+                    (h10, h01, diagonal),
+                ]
+                .into_iter()
+                .all(|(a, b, run)| walk.can_move(run, b - a));
+                let lowest = h00.min(h10).min(h01).min(h11);
+                let wadable = water_level
+                    .zip(agent.max_wade_depth)
+                    .is_none_or(|(level, depth)| level - lowest <= depth);
+                walkable[row * cols + col] = passable && wadable;
+            }
+        }
+        if agent.agent_radius > 0.0 {
+            erode(&mut walkable, cols, rows, agent.agent_radius / cell);
+        }
+
+        // Greedy rectangles: as wide as possible, then as deep as possible
+        let mut cell_polygons = vec![NO_POLYGON; cols * rows];
+        let mut polygons = Vec::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                let free = |cell_polygons: &[u32], c: usize, r: usize| {
+                    walkable[r * cols + c] && cell_polygons[r * cols + c] == NO_POLYGON
+                };
+                if !free(&cell_polygons, col, row) {
+                    continue;
+                }
+                let mut width = 1;
+                while col + width < cols
+                    && width < MAX_POLYGON_CELLS
+                    && free(&cell_polygons, col + width, row)
+                    && is_planar(mosaic, col, row, width + 1, 1)
+                {
+                    width += 1;
+                }
+                let mut depth = 1;
+                while row + depth < rows
+                    && depth < MAX_POLYGON_CELLS
+                    && (col..col + width).all(|c| free(&cell_polygons, c, row + depth))
+                    && is_planar(mosaic, col, row, width, depth + 1)
+                {
+                    depth += 1;
+                }
+
+                let id = polygons.len() as u32;
+                for r in row..row + depth {
+                    cell_polygons[r * cols + col..r * cols + col + width].fill(id);
+                }
+                polygons.push(Polygon {
+                    col,
+                    row,
+                    cols: width,
+                    rows: depth,
+                    portals: Vec::new(),
+                });
+            }
+        }
+
+        // Neighbouring rectangles share one straight stretch of border
+        let node = |c: usize, r: usize| {
+            [
+                mosaic.min_x + c as f32 * cell,
+                mosaic.min_z + r as f32 * cell,
+            ]
+        };
+        let mut shared: BTreeMap<(u32, u32), ([f32; 2], [f32; 2])> = BTreeMap::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                let p = cell_polygons[row * cols + col];
+                if p == NO_POLYGON {
+                    continue;
+                }
+                let mut neighbours = Vec::with_capacity(2);
+                if col + 1 < cols {
+                    let edge = (node(col + 1, row), node(col + 1, row + 1));
+                    neighbours.push((cell_polygons[row * cols + col + 1], edge));
+                }
+                if row + 1 < rows {
+                    let edge = (node(col, row + 1), node(col + 1, row + 1));
+                    neighbours.push((cell_polygons[(row + 1) * cols + col], edge));
+                }
+                for (q, (a, b)) in neighbours {
+                    if q == NO_POLYGON || q == p {
+                        continue;
+                    }
+                    let span = shared.entry((p.min(q), p.max(q))).or_insert((a, b));
+                    span.0 = [span.0[0].min(a[0]), span.0[1].min(a[1])];
+                    span.1 = [span.1[0].max(b[0]), span.1[1].max(b[1])];
+                }
+            }
+        }
+        for ((p, q), (a, b)) in shared {
+            let (p, q) = (p as usize, q as usize);
+            polygons[p].portals.push(Portal { to: q, a, b });
+            polygons[q].portals.push(Portal { to: p, a, b });
+        }
+
+        let mesh = Self::triangulate(mosaic, &polygons);
+        let walkable_area = polygons
+            .iter()
+            .map(|p| (p.cols * p.rows) as f32 * cell * cell)
+            .sum();
+
+        NavMesh {
+            agent,
+            min_x: mosaic.min_x,
+            min_z: mosaic.min_z,
+            cell_size: cell,
+            cols,
+            rows,
+            cell_polygons,
+            polygons,
+            mesh,
+            walkable_area,
+            terrain_area,
+        }
+    }
+
+    /// Triangles over the polygons' corner nodes, which are shared between
+    /// polygons. Where a neighbour's corner lies on a polygon's border the
+    /// border is split there, so neighbours of different spans meet without
+    /// T-junctions; such polygons are fanned from their centre, the others
+    /// get two triangles.
+    fn triangulate(mosaic: &Mosaic, polygons: &[Polygon]) -> TriangleMesh {
+        let corners: HashSet<(usize, usize)> = polygons
+            .iter()
+            .flat_map(|p| {
+                [
+                    (p.col, p.row),
+                    (p.col, p.row + p.rows),
+                    (p.col + p.cols, p.row + p.rows),
+                    (p.col + p.cols, p.row),
+                ]
+            })
+            .collect();
+
+        let mut vertices: HashMap<(usize, usize), u32> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut indices = Vec::with_capacity(polygons.len() * 6);
+        for p in polygons {
+            let (c0, r0, c1, r1) = (p.col, p.row, p.col + p.cols, p.row + p.rows);
+            // Border nodes that are somebody's corner, going round the same
+            // way as the two-triangle winding below
+            let border: Vec<(usize, usize)> = (r0..r1)
+                .map(|r| (c0, r))
+                .chain((c0..c1).map(|c| (c, r1)))
+                .chain((r0 + 1..=r1).rev().map(|r| (c1, r)))
+                .chain((c0 + 1..=c1).rev().map(|c| (c, r0)))
+                .filter(|node| corners.contains(node))
+                .collect();
+            let mut vertex = |(c, r): (usize, usize)| {
+                *vertices.entry((c, r)).or_insert_with(|| {
+                    positions.push([
+                        mosaic.min_x + c as f32 * mosaic.cell_size,
+                        mosaic.get(c, r).unwrap_or(0.0),
+                        mosaic.min_z + r as f32 * mosaic.cell_size,
+                    ]);
+                    (positions.len() - 1) as u32
+                })
+            };
+            let border: Vec<u32> = border.into_iter().map(&mut vertex).collect();
+            if let [a, b, b1, a1] = border[..] {
+                indices.extend_from_slice(&[a, b, a1, a1, b, b1]);
+                continue;
+            }
+
+            let (x, z) = (
+                mosaic.min_x + (c0 + c1) as f32 / 2.0 * mosaic.cell_size,
+                mosaic.min_z + (r0 + r1) as f32 / 2.0 * mosaic.cell_size,
+            );
+            positions.push([x, mosaic.sample(x, z).unwrap_or(0.0), z]);
+            let centre = (positions.len() - 1) as u32;
+            for (i, &a) in border.iter().enumerate() {
+                indices.extend_from_slice(&[centre, a, border[(i + 1) % border.len()]]);
+            }
+        }
+
+        // Area-weighted face normals, pointing up
+        let mut normals = vec![[0.0; 3]; positions.len()];
+        for tri in indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| positions[i as usize]);
+            let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let mut n = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            if n[1] < 0.0 {
+                n = n.map(|c| -c);
+            }
+            for &i in tri {
+                let sum = &mut normals[i as usize];
+                *sum = [0, 1, 2].map(|k| sum[k] + n[k]);
+            }
+        }
+
+        TriangleMesh {
+            positions,
+            normals: normals.into_iter().map(normalize).collect(),
+            indices,
+        }
+    }
+
+    fn polygon_at(&self, [x, z]: [f32; 2]) -> Option<usize> {
+        let u = (x - self.min_x) / self.cell_size;
+        let v = (z - self.min_z) / self.cell_size;
+        if !(u >= 0.0 && v >= 0.0) {
+            return None;
+        }
+        // Points on the far edge belong to the last cell
+        let col = (u as usize).min(self.cols.saturating_sub(1));
+        let row = (v as usize).min(self.rows.saturating_sub(1));
+        if u > self.cols as f32 || v > self.rows as f32 {
+            return None;
+        }
+        let polygon = self.cell_polygons[row * self.cols + col];
+        (polygon != NO_POLYGON).then_some(polygon as usize)
+    }
+
+    fn center(&self, polygon: &Polygon) -> [f32; 2] {
+        [
+            self.min_x + (polygon.col as f32 + polygon.cols as f32 / 2.0) * self.cell_size,
+            self.min_z + (polygon.row as f32 + polygon.rows as f32 / 2.0) * self.cell_size,
+        ]
+    }
+
+    /// Polygons crossed from `start` to `goal`, with the portal used to enter
+    /// each one after the first. A* between the points where the portals
+    /// are crossed.
+    fn corridor(
+        &self,
+        start: usize,
+        goal: usize,
+        from: [f32; 2],
+        to: [f32; 2],
+        visited: &mut usize,
+    ) -> Option<Vec<(usize, usize)>> {
+        // Cost, previous polygon, portal index in it, and entry point
+        let mut best: HashMap<usize, (f32, usize, usize, [f32; 2])> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        best.insert(start, (0.0, start, 0, from));
+        open.push(Reverse((distance(from, to).to_bits(), start)));
+
+        while let Some(Reverse((estimate, polygon))) = open.pop() {
+            let (cost, _, _, entry) = best[&polygon];
+            if estimate != (cost + distance(entry, to)).to_bits() {
+                continue;
+            }
+            if polygon == goal {
+                let mut corridor = Vec::new();
+                let mut current = goal;
+                while current != start {
+                    let (_, previous, portal, _) = best[&current];
+                    corridor.push((previous, portal));
+                    current = previous;
+                }
+                corridor.reverse();
+                return Some(corridor);
+            }
+            *visited += 1;
+
+            for (index, portal) in self.polygons[polygon].portals.iter().enumerate() {
+                let middle = crossing(entry, to, portal);
+                let next_cost = cost + distance(entry, middle);
+                if best
+                    .get(&portal.to)
+                    .is_none_or(|&(known, ..)| next_cost < known)
+                {
+                    best.insert(portal.to, (next_cost, polygon, index, middle));
+                    open.push(Reverse((
+                        (next_cost + distance(middle, to)).to_bits(),
+                        portal.to,
+                    )));
+                }
+            }
+        }
+        None
+    }
+
+    /// Shortest path from `from` to `to` as `[x, z]` corners, or `None` if
+    /// either point is off the mesh or they aren't connected. `visited`
+    /// counts the polygons the search expanded.
+    pub fn find_path(
+        &self,
+        from: [f32; 2],
+        to: [f32; 2],
+        visited: &mut usize,
+    ) -> Result<Option<Vec<[f32; 2]>>, ApiError> {
+        let start = self
+            .polygon_at(from)
+            .ok_or_else(|| ApiError::BadRequest("start is not on the navmesh".to_string()))?;
+        let goal = self
+            .polygon_at(to)
+            .ok_or_else(|| ApiError::BadRequest("end is not on the navmesh".to_string()))?;
+        let Some(corridor) = self.corridor(start, goal, from, to, visited) else {
+            return Ok(None);
+        };
+
+        // Portals as (left, right) seen while walking through them
+        let mut portals = vec![(from, from)];
+        for (polygon, portal) in corridor {
+            let here = &self.polygons[polygon];
+            let portal = &here.portals[portal];
+            let direction = sub(self.center(&self.polygons[portal.to]), self.center(here));
+            if cross(direction, sub(portal.a, portal.b)) > 0.0 {
+                portals.push((portal.a, portal.b));
+            } else {
+                portals.push((portal.b, portal.a));
+            }
+        }
+        portals.push((to, to));
+        Ok(Some(string_pull(&portals)))
+    }
+}
+
+/// The funnel algorithm: the taut path through `portals`, which run from a
+/// degenerate portal at the start to one at the end. "Left" is where the
+/// cross product with the walking direction is positive.
+fn string_pull(portals: &[([f32; 2], [f32; 2])]) -> Vec<[f32; 2]> {
+    let mut path = vec![portals[0].0];
+    let (mut apex, mut left, mut right) = (portals[0].0, portals[0].0, portals[0].1);
+    let (mut left_index, mut right_index) = (0, 0);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let (next_left, next_right) = portals[i];
+
+        // Narrow the funnel from the right
+        if cross(sub(right, apex), sub(next_right, apex)) >= 0.0 {
+            if same_point(apex, right) || cross(sub(left, apex), sub(next_right, apex)) < 0.0 {
+                right = next_right;
+                right_index = i;
+            } else {
+                // The right side crossed the left one: its corner is on the path
+                push_corner(&mut path, left);
+                apex = left;
+                let apex_index = left_index;
+                (left, right) = (apex, apex);
+                (left_index, right_index) = (apex_index, apex_index);
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        // Narrow the funnel from the left
+        if cross(sub(left, apex), sub(next_left, apex)) <= 0.0 {
+            if same_point(apex, left) || cross(sub(right, apex), sub(next_left, apex)) > 0.0 {
+                left = next_left;
+                left_index = i;
+            } else {
+                push_corner(&mut path, right);
+                apex = right;
+                let apex_index = right_index;
+                (left, right) = (apex, apex);
+                (left_index, right_index) = (apex_index, apex_index);
+                i = apex_index + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    push_corner(&mut path, portals[portals.len() - 1].0);
+    path
+}
+
+fn push_corner(path: &mut Vec<[f32; 2]>, corner: [f32; 2]) {
+    if path.last().is_none_or(|&last| !same_point(last, corner)) {
+        path.push(corner);
+    }
+}
+
+impl LoadedMap {
+    /// The navmesh for `agent`, built on first use. A few are kept per map.
+    pub fn navmesh(&self, agent: &AgentProfile) -> Result<Arc<NavMesh>, ApiError> {
+        if let Some(mesh) = self.cached_navmesh(agent) {
+            return Ok(mesh);
+        }
+        let water_level = self.meta.as_ref().and_then(|m| m.water_level);
+        let mesh = Arc::new(NavMesh::new(self.mosaic()?, water_level, *agent));
+
+        let mut cache = self.navmeshes.lock().unwrap();
+        if let Some(existing) = cache.iter().find(|m| m.agent == *agent) {
+            // Built concurrently by another request
+            return Ok(existing.clone());
+        }
+        if cache.len() >= NAVMESH_CACHE_SIZE {
+            cache.remove(0);
+        }
+        cache.push(mesh.clone());
+        Ok(mesh)
+    }
+
+    fn cached_navmesh(&self, agent: &AgentProfile) -> Option<Arc<NavMesh>> {
+        let cache = self.navmeshes.lock().unwrap();
+        cache.iter().find(|m| m.agent == *agent).cloned()
+    }
+}
+
+/// A navmesh as triangles for overlays, with flat `[x, y, z, …]` positions.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NavMeshResponse {
+    scene: String,
+    agent: AgentProfile,
+    polygons: usize,
+    triangles: usize,
+    walkable_area: f32,
+    /// Share of the terrain that is walkable, from 0 to 1.
+    coverage: f32,
+    positions: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+async fn build(
+    state: &AppState,
+    name: &str,
+    agent: AgentProfile,
+) -> Result<(Arc<LoadedMap>, Arc<NavMesh>), ApiError> {
+    agent.validate()?;
+    let map = state.map(name).await?;
+    let loaded = map.clone();
+    let navmesh = tokio::task::spawn_blocking(move || loaded.navmesh(&agent))
+        .await
+        .map_err(|_| ApiError::Internal("Navmesh generation failed".to_string()))??;
+    Ok((map, navmesh))
+}
+
+pub async fn get_navmesh(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(agent): Query<AgentProfile>,
+) -> Result<Json<NavMeshResponse>, ApiError> {
+    let (map, navmesh) = build(&state, &name, agent).await?;
+    let coverage = if navmesh.terrain_area > 0.0 {
+        navmesh.walkable_area / navmesh.terrain_area
+    } else {
+        0.0
+    };
+    Ok(Json(NavMeshResponse {
+        scene: map.data.scene.clone(),
+        agent: navmesh.agent,
+        polygons: navmesh.polygons.len(),
+        triangles: navmesh.mesh.indices.len() / 3,
+        walkable_area: navmesh.walkable_area,
+        coverage,
+        positions: navmesh.mesh.positions.iter().flatten().copied().collect(),
+        indices: navmesh.mesh.indices.clone(),
+    }))
+}
+
+pub async fn export_navmesh_obj(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(agent): Query<AgentProfile>,
+) -> Result<Response, ApiError> {
+    let (map, navmesh) = build(&state, &name, agent).await?;
+    let object = format!("{}_navmesh", map.data.scene);
+    let body = export::meshes_to_obj(&map.data.scene, &[(&object, &navmesh.mesh)]);
+    Ok(export::attachment(
+        body,
+        "model/obj",
+        &format!("{}.obj", object),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{MapFile, TerrainChunk};
+
+    fn mosaic(heights: Vec<f32>) -> Mosaic {
+        let map = MapFile {
+            scene: "test".to_string(),
+            terrains: vec![TerrainChunk::from_heights("a", 0.0, 0.0, heights)],
+        };
+        Mosaic::new(&map).unwrap()
+    }
+
+    /// `size`² nodes of flat ground with a block of height 10 on the nodes
+    /// from `from` to `to` in both directions.
+    fn block(size: usize, from: usize, to: usize) -> Mosaic {
+        let raised = |i: usize| (from..=to).contains(&i);
+        mosaic(
+            (0..size * size)
+                .map(|i| {
+                    if raised(i % size) && raised(i / size) {
+                        10.0
+                    } else {
+                        0.0
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn agent(radius: f32) -> AgentProfile {
+        AgentProfile {
+            agent_radius: radius,
+            agent_height: 2.0,
+            max_wade_depth: None,
+            max_slope: 45.0,
+            step_height: 0.4,
+        }
+    }
+
+    #[test]
+    fn erode_keeps_clear_of_edges_and_holes() {
+        let (cols, rows) = (7, 7);
+        let mut walkable = vec![true; cols * rows];
+        walkable[3 * cols + 3] = false;
+        erode(&mut walkable, cols, rows, 0.6);
+
+        let expected: Vec<bool> = (0..cols * rows)
+            .map(|i| {
+                let (col, row) = (i % cols, i / cols);
+                let on_edge = col == 0 || row == 0 || col == cols - 1 || row == rows - 1;
+                // Cells beside the hole share an edge with it, diagonal
+                // neighbours only a corner
+                let beside_hole = col.abs_diff(3) + row.abs_diff(3) <= 1;
+                !on_edge && !beside_hole
+            })
+            .collect();
+        assert_eq!(walkable, expected);
+    }
+
+    #[test]
+    fn flat_ground_merges_into_one_polygon() {
+        let navmesh = NavMesh::new(&mosaic(vec![1.5; 25]), None, agent(0.0));
+        assert_eq!(navmesh.polygons.len(), 1);
+        let polygon = &navmesh.polygons[0];
+        assert_eq!((polygon.cols, polygon.rows), (4, 4));
+        assert_eq!(navmesh.walkable_area, 16.0);
+        assert_eq!(navmesh.mesh.indices.len(), 6);
+    }
+
+    #[test]
+    fn wading_is_only_limited_by_max_wade_depth() {
+        let ground = mosaic(vec![0.0; 9]);
+        let navmesh = NavMesh::new(&ground, Some(1.0), agent(0.0));
+        assert_eq!(navmesh.walkable_area, 4.0);
+
+        let shallow = AgentProfile {
+            max_wade_depth: Some(0.5),
+            ..agent(0.0)
+        };
+        let navmesh = NavMesh::new(&ground, Some(1.0), shallow);
+        assert_eq!(navmesh.walkable_area, 0.0);
+    }
+
+    #[test]
+    fn shared_borders_use_the_same_vertices() {
+        // One tall polygon beside two short ones, whose shared corner lies
+        // halfway along its border
+        #[rustfmt::skip]
+        let mosaic = mosaic(vec![
+            0.0, 0.3, 0.1,
+            0.2, 0.5, 0.4,
+            0.1, 0.2, 0.0,
+        ]);
+        let rectangle = |col, row, cols, rows| Polygon {
+            col,
+            row,
+            cols,
+            rows,
+            portals: Vec::new(),
+        };
+        let polygons = [
+            rectangle(0, 0, 1, 2),
+            rectangle(1, 0, 1, 1),
+            rectangle(1, 1, 1, 1),
+        ];
+        let mesh = NavMesh::triangulate(&mosaic, &polygons);
+
+        // Without T-junctions every inner edge is shared by two triangles
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for tri in mesh.indices.chunks_exact(3) {
+            for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        for ((a, b), count) in edges {
+            let [pa, pb] = [a, b].map(|i| mesh.positions[i as usize]);
+            let outer = |i: usize| (pa[i] == 0.0 || pa[i] == 2.0) && pa[i] == pb[i];
+            assert!(
+                count == 2 || outer(0) || outer(2),
+                "edge {:?}–{:?} is used {} times",
+                pa,
+                pb,
+                count
+            );
+        }
+        // The corner shared by all three is one vertex at the mosaic height
+        let at_corner: Vec<_> = mesh
+            .positions
+            .iter()
+            .filter(|p| p[0] == 1.0 && p[2] == 1.0)
+            .collect();
+        assert_eq!(at_corner, [&[1.0, 0.5, 1.0]]);
+    }
+
+    #[test]
+    fn string_pull_goes_straight_through_aligned_portals() {
+        let portals = [
+            ([0.0, 0.0], [0.0, 0.0]),
+            ([1.0, 1.0], [1.0, -1.0]),
+            ([2.0, 1.0], [2.0, -1.0]),
+            ([3.0, 0.5], [3.0, 0.5]),
+        ];
+        assert_eq!(string_pull(&portals), [[0.0, 0.0], [3.0, 0.5]]);
+    }
+
+    #[test]
+    fn paths_bend_around_block_corners() {
+        // Cells touching the raised nodes 3..=5 are too steep, leaving a
+        // block from 2 to 6
+        let navmesh = NavMesh::new(&block(9, 3, 5), None, agent(0.0));
+        let path = navmesh
+            .find_path([1.0, 4.0], [7.0, 4.0], &mut 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.len(), 4, "{:?}", path);
+        let side = path[1][1];
+        assert!(side == 2.0 || side == 6.0, "{:?}", path);
+        assert_eq!(&path[1..3], [[2.0, side], [6.0, side]]);
+    }
+}
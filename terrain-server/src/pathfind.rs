@@ -0,0 +1,426 @@
+//! Walking paths across a map's mosaic.
+//!
+//! `POST /api/maps/{name}/path` runs A* over the mosaic's grid nodes with
+//! 8-connected moves by default. A move is walkable when its slope is within
+//! the agent's `maxSlope`, or when its rise is no more than `stepHeight`,
+//! which lets agents over small ledges and bumps. No-data nodes are never
+//! entered and diagonal moves may not cut past them. With `"mode":
+//! "navmesh"` the path is searched on the agent's navmesh instead.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::Arc,
+};
+
+use crate::{AppState, api::ApiError, mosaic::Mosaic, navmesh::AgentProfile};
+
+/// Upper bound on the nodes one search may expand.
+const MAX_VISITED_NODES: usize = 4 * 1024 * 1024;
+
+/// How far, in world units, a merged segment may stray from the terrain.
+const HEIGHT_TOLERANCE: f32 = 0.01;
+
+/// How steep the terrain an agent can walk on may be; see `AgentProfile`.
+#[derive(Clone, Copy, Debug)]
+pub struct WalkProfile {
+    /// Steepest walkable slope, in degrees.
+    pub max_slope: f32,
+    /// Highest rise an agent can step up or down regardless of slope.
+    pub step_height: f32,
+}
+
+impl WalkProfile {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if !(self.max_slope.is_finite() && (0.0..90.0).contains(&self.max_slope)) {
+            return Err(ApiError::BadRequest(
+                "maxSlope must be at least 0 and less than 90 degrees".to_string(),
+            ));
+        }
+        if !(self.step_height.is_finite() && self.step_height >= 0.0) {
+            return Err(ApiError::BadRequest(
+                "stepHeight must be a non-negative number".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether an agent can move `run` world units horizontally while
+    /// climbing or descending `rise`.
+    pub fn can_move(&self, run: f32, rise: f32) -> bool {
+        let rise = rise.abs();
+        rise <= self.step_height || rise <= run * self.max_slope.to_radians().tan()
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathMode {
+    /// A* over the mosaic's nodes
+    #[default]
+    Grid,
+    /// A* over navmesh polygons, straightened
+    Navmesh,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathRequest {
+    /// `[x, z]` of the start.
+    start: [f32; 2],
+    /// `[x, z]` of the goal.
+    end: [f32; 2],
+    #[serde(default)]
+    mode: PathMode,
+    /// Grid paths only use the slope and step limits.
+    #[serde(flatten)]
+    agent: AgentProfile,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathResponse {
+    scene: String,
+    /// Whether the goal can be reached; `waypoints` is empty if not.
+    found: bool,
+    /// Walked distance along the surface.
+    length: f32,
+    /// `[x, y, z]` points of the path, from start to end. Runs that are
+    /// straight both across the grid and in height are merged.
+    waypoints: Vec<[f32; 3]>,
+    /// Grid nodes or navmesh polygons expanded by the search.
+    visited: usize,
+}
+
+/// Found path over mosaic nodes, as node indices from start to goal.
+struct GridPath {
+    nodes: Vec<usize>,
+    length: f32,
+}
+
+/// A* from node `start` to node `goal`. `Ok(None)` means the goal can't be
+/// reached; `Err` that the search expanded too many nodes.
+fn search(
+    mosaic: &Mosaic,
+    profile: &WalkProfile,
+    start: usize,
+    goal: usize,
+    visited: &mut usize,
+) -> Result<Option<GridPath>, ApiError> {
+    let cols = mosaic.cols;
+    let cell = mosaic.cell_size;
+    let height = |node: usize| mosaic.get(node % cols, node / cols);
+    let heuristic = |node: usize| {
+        let dc = (node % cols) as f32 - (goal % cols) as f32;
+        let dr = (node / cols) as f32 - (goal / cols) as f32;
+        dc.hypot(dr) * cell
+    };
+
+    // Best known cost and predecessor of each reached node
+    let mut best: HashMap<usize, (f32, usize)> = HashMap::new();
+    // Costs are non-negative, so their bit patterns sort like the floats
+    let mut open = BinaryHeap::new();
+    best.insert(start, (0.0, start));
+    open.push(Reverse((heuristic(start).to_bits(), start)));
+
+    while let Some(Reverse((estimate, node))) = open.pop() {
+        // Nodes are pushed again when a cheaper way to them is found; skip
+        // the outdated entries
+        if estimate != (best[&node].0 + heuristic(node)).to_bits() {
+            continue;
+        }
+        if node == goal {
+            let length = best[&goal].0;
+            let mut nodes = vec![goal];
+            let mut current = goal;
+            while current != start {
+                current = best[&current].1;
+                nodes.push(current);
+            }
+            nodes.reverse();
+            return Ok(Some(GridPath { nodes, length }));
+        }
+        *visited += 1;
+        if *visited > MAX_VISITED_NODES {
+            return Err(ApiError::BadRequest(format!(
+                "search gave up after {} nodes; try points closer together",
+                MAX_VISITED_NODES
+            )));
+        }
+
+        let cost = best[&node].0;
+        let (col, row) = ((node % cols) as isize, (node / cols) as isize);
+        let here = height(node).expect("only nodes with data are opened");
+        for (dc, dr) in [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (1, -1),
+            (-1, 1),
+            (1, 1),
+        ] {
+            let (next_col, next_row) = (col + dc, row + dr);
+            if next_col < 0 || next_row < 0 {
+                continue;
+            }
+            let Some(there) = mosaic.get(next_col as usize, next_row as usize) else {
+                continue;
+            };
+            if dc != 0
+                && dr != 0
+                && (mosaic.get(next_col as usize, row as usize).is_none()
+                    || mosaic.get(col as usize, next_row as usize).is_none())
+            {
+                continue;
+            }
+            let run = if dc != 0 && dr != 0 {
+                cell * std::f32::consts::SQRT_2
+            } else {
+                cell
+            };
+            let rise = there - here;
+            if !profile.can_move(run, rise) {
+                continue;
+            }
+
+            let next = next_row as usize * cols + next_col as usize;
+            let next_cost = cost + run.hypot(rise);
+            if best.get(&next).is_none_or(|&(known, _)| next_cost < known) {
+                best.insert(next, (next_cost, node));
+                open.push(Reverse(((next_cost + heuristic(next)).to_bits(), next)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Grid node nearest to `(x, z)` if it has data.
+fn nearest_node(mosaic: &Mosaic, [x, z]: [f32; 2]) -> Option<usize> {
+    let col = ((x - mosaic.min_x) / mosaic.cell_size).round();
+    let row = ((z - mosaic.min_z) / mosaic.cell_size).round();
+    if !(col >= 0.0 && row >= 0.0) {
+        return None;
+    }
+    let (col, row) = (col as usize, row as usize);
+    mosaic.get(col, row).map(|_| row * mosaic.cols + col)
+}
+
+/// Drops the nodes in the middle of straight, evenly sloped runs and swaps
+/// the end nodes for the requested points.
+fn waypoints(mosaic: &Mosaic, path: &GridPath, start: [f32; 2], end: [f32; 2]) -> Vec<[f32; 3]> {
+    let cols = mosaic.cols;
+    let position = |node: usize| {
+        let x = mosaic.min_x + (node % cols) as f32 * mosaic.cell_size;
+        let z = mosaic.min_z + (node / cols) as f32 * mosaic.cell_size;
+        [x, z]
+    };
+    let height = |node: usize| mosaic.get(node % cols, node / cols).unwrap_or(0.0);
+    let step = |a: usize, b: usize| {
+        (
+            (b % cols) as isize - (a % cols) as isize,
+            (b / cols) as isize - (a / cols) as isize,
+        )
+    };
+
+    // Each point with the node it stands for
+    let mut points = vec![(start, path.nodes[0])];
+    for i in 1..path.nodes.len().saturating_sub(1) {
+        let (prev, node, next) = (path.nodes[i - 1], path.nodes[i], path.nodes[i + 1]);
+        let bend = height(node) - (height(prev) + height(next)) / 2.0;
+        if step(prev, node) != step(node, next) || bend.abs() > HEIGHT_TOLERANCE {
+            points.push((position(node), node));
+        }
+    }
+    points.push((end, path.nodes[path.nodes.len() - 1]));
+
+    points
+        .into_iter()
+        .map(|([x, z], node)| {
+            // Requested points may lie up to half a cell off the grid, where
+            // the node they snapped to is the nearest terrain
+            let y = mosaic.sample(x, z).unwrap_or_else(|| height(node));
+            [x, y, z]
+        })
+        .collect()
+}
+
+/// Lays the `[x, z]` corners of a path onto the terrain. Segments are
+/// sampled at the mosaic's spacing and the samples kept where the terrain
+/// bends.
+pub fn drape(mosaic: &Mosaic, corners: &[[f32; 2]]) -> Vec<[f32; 3]> {
+    let point = |[x, z]: [f32; 2]| [x, mosaic.sample(x, z).unwrap_or(f32::NAN), z];
+    let Some(&first) = corners.first() else {
+        return Vec::new();
+    };
+    let mut points = vec![point(first)];
+    for pair in corners.windows(2) {
+        let ([x0, z0], [x1, z1]) = (pair[0], pair[1]);
+        let steps = ((x1 - x0).hypot(z1 - z0) / mosaic.cell_size)
+            .ceil()
+            .max(1.0) as usize;
+        let samples: Vec<[f32; 3]> = (1..=steps)
+            .map(|i| {
+                let t = i as f32 / steps as f32;
+                point([x0 + (x1 - x0) * t, z0 + (z1 - z0) * t])
+            })
+            .collect();
+        for (i, sample) in samples.iter().enumerate() {
+            // The segment's end is a corner and always kept
+            let Some(next) = samples.get(i + 1) else {
+                points.push(*sample);
+                break;
+            };
+            let previous = points[points.len() - 1];
+            let bend = sample[1] - (previous[1] + next[1]) / 2.0;
+            if bend.abs() > HEIGHT_TOLERANCE {
+                points.push(*sample);
+            }
+        }
+    }
+    points
+}
+
+fn polyline_length(points: &[[f32; 3]]) -> f32 {
+    points
+        .windows(2)
+        .map(|pair| {
+            let [a, b] = [pair[0], pair[1]];
+            let (dx, dy, dz) = (b[0] - a[0], b[1] - a[1], b[2] - a[2]);
+            (dx * dx + dy * dy + dz * dz).sqrt()
+        })
+        .sum()
+}
+
+pub async fn find_path(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<PathRequest>,
+) -> Result<Json<PathResponse>, ApiError> {
+    req.agent.validate()?;
+    let map = state.map(&name).await?;
+
+    tokio::task::spawn_blocking(move || {
+        let mosaic = map.mosaic()?;
+        let mut visited = 0;
+        let (found, length, waypoints) = match req.mode {
+            PathMode::Grid => {
+                let start = nearest_node(mosaic, req.start).ok_or_else(|| {
+                    ApiError::BadRequest("start is not on the terrain".to_string())
+                })?;
+                let goal = nearest_node(mosaic, req.end)
+                    .ok_or_else(|| ApiError::BadRequest("end is not on the terrain".to_string()))?;
+                match search(mosaic, &req.agent.walk(), start, goal, &mut visited)? {
+                    Some(path) => (
+                        true,
+                        path.length,
+                        waypoints(mosaic, &path, req.start, req.end),
+                    ),
+                    None => (false, 0.0, Vec::new()),
+                }
+            }
+            PathMode::Navmesh => {
+                let navmesh = map.navmesh(&req.agent)?;
+                match navmesh.find_path(req.start, req.end, &mut visited)? {
+                    Some(corners) => {
+                        let waypoints = drape(mosaic, &corners);
+                        (true, polyline_length(&waypoints), waypoints)
+                    }
+                    None => (false, 0.0, Vec::new()),
+                }
+            }
+        };
+        Ok(Json(PathResponse {
+            scene: map.data.scene.clone(),
+            found,
+            length,
+            waypoints,
+            visited,
+        }))
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Path search failed".to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{MapFile, TerrainChunk};
+
+    /// 5×5 mosaic with a wall of height 3 along x = 2, open at the rows in
+    /// `gaps`.
+    fn walled(gaps: &[usize]) -> Mosaic {
+        let heights = (0..25)
+            .map(|i| {
+                let (col, row) = (i % 5, i / 5);
+                if col == 2 && !gaps.contains(&row) {
+                    3.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        Mosaic::new(&MapFile {
+            scene: "test".to_string(),
+            terrains: vec![TerrainChunk::from_heights("a", 0.0, 0.0, heights)],
+        })
+        .unwrap()
+    }
+
+    fn walk(max_slope: f32) -> WalkProfile {
+        WalkProfile {
+            max_slope,
+            step_height: 0.0,
+        }
+    }
+
+    #[test]
+    fn slopes_above_max_slope_block_the_way() {
+        let mosaic = walled(&[]);
+        let (start, goal) = (2 * 5, 2 * 5 + 4);
+        let mut visited = 0;
+        // A rise of 3 over one cell is about 72°
+        let path = search(&mosaic, &walk(60.0), start, goal, &mut visited).unwrap();
+        assert!(path.is_none());
+
+        let path = search(&mosaic, &walk(75.0), start, goal, &mut visited)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.nodes, [10, 11, 12, 13, 14]);
+    }
+
+    #[test]
+    fn paths_detour_through_gaps_in_a_steep_wall() {
+        let mosaic = walled(&[4]);
+        let mut visited = 0;
+        let path = search(&mosaic, &walk(45.0), 2 * 5, 2 * 5 + 4, &mut visited)
+            .unwrap()
+            .unwrap();
+        assert!(path.nodes.contains(&(4 * 5 + 2)));
+        assert!(
+            path.nodes
+                .iter()
+                .all(|&node| mosaic.get(node % 5, node / 5) == Some(0.0))
+        );
+        // Two diagonals down to the gap and two back up
+        assert!((path.length - 4.0 * std::f32::consts::SQRT_2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ends_off_the_grid_take_the_height_of_their_node() {
+        let mosaic = walled(&[]);
+        let mut visited = 0;
+        let path = search(&mosaic, &walk(75.0), 0, 2, &mut visited)
+            .unwrap()
+            .unwrap();
+        let points = waypoints(&mosaic, &path, [-0.4, 0.0], [2.0, -0.4]);
+        assert_eq!(points.first(), Some(&[-0.4, 0.0, 0.0]));
+        assert_eq!(points.last(), Some(&[2.0, 3.0, -0.4]));
+    }
+}
@@ -0,0 +1,238 @@
+//! Elevation profiles along a route.
+//!
+//! `POST /api/maps/{name}/profile` walks a polyline of `[x, z]` points and
+//! samples the mosaic every `spacing` world units of horizontal distance, so
+//! the profile runs across chunk borders without steps. Each sample is a
+//! `[distance, height, slope]` tuple; the slope is the grade along the route
+//! in degrees, positive uphill in the direction of travel.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{AppState, api::ApiError, mosaic::Mosaic};
+
+/// Upper bound on the samples in one profile.
+const MAX_PROFILE_SAMPLES: usize = 1024 * 1024;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRequest {
+    /// `[x, z]` points of the route, at least two.
+    points: Vec<[f32; 2]>,
+    /// Horizontal distance between samples; the mosaic's spacing by default.
+    spacing: Option<f32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileResponse {
+    scene: String,
+    spacing: f32,
+    /// Horizontal length of the route.
+    length: f32,
+    /// Total height gained and lost between samples with data.
+    ascent: f32,
+    descent: f32,
+    /// `[distance, height, slope]` from the first point to the last, every
+    /// `spacing` units plus the route's end. Height and slope are `null`
+    /// where no terrain covers the route.
+    samples: Vec<(f32, Option<f32>, Option<f32>)>,
+}
+
+/// `[x, z]` positions every `spacing` along the polyline and their distance
+/// from its start. The last point is always included.
+fn positions(points: &[[f32; 2]], spacing: f32) -> Vec<(f32, [f32; 2])> {
+    let mut positions = Vec::new();
+    let mut walked = 0.0;
+    // Distance of the next sample from the start of the route
+    let mut next = 0.0;
+    for pair in points.windows(2) {
+        let ([x0, z0], [x1, z1]) = (pair[0], pair[1]);
+        let length = (x1 - x0).hypot(z1 - z0);
+        while next <= walked + length {
+            let t = if length > 0.0 {
+                (next - walked) / length
+            } else {
+                0.0
+            };
+            positions.push((next, [x0 + (x1 - x0) * t, z0 + (z1 - z0) * t]));
+            next = positions.len() as f32 * spacing;
+        }
+        walked += length;
+    }
+    // Unless a sample already landed on it, give or take rounding
+    if positions
+        .last()
+        .is_none_or(|&(d, _)| walked - d > spacing * 1e-3)
+    {
+        positions.push((walked, points[points.len() - 1]));
+    }
+    positions
+}
+
+fn profile(scene: String, mosaic: &Mosaic, points: &[[f32; 2]], spacing: f32) -> ProfileResponse {
+    let positions = positions(points, spacing);
+    let heights: Vec<Option<f32>> = positions
+        .iter()
+        .map(|&(_, [x, z])| mosaic.sample(x, z))
+        .collect();
+
+    let mut samples = Vec::with_capacity(positions.len());
+    let (mut ascent, mut descent) = (0.0, 0.0);
+    for (i, &(distance, _)) in positions.iter().enumerate() {
+        // Central differences, one-sided at the ends and next to no-data
+        let before = i
+            .checked_sub(1)
+            .and_then(|j| heights[j].map(|h| (positions[j].0, h)));
+        let after = heights
+            .get(i + 1)
+            .copied()
+            .flatten()
+            .map(|h| (positions[i + 1].0, h));
+        let slope = heights[i].and_then(|here| {
+            let (d0, h0) = before.unwrap_or((distance, here));
+            let (d1, h1) = after.unwrap_or((distance, here));
+            (d1 > d0).then(|| ((h1 - h0) / (d1 - d0)).atan().to_degrees())
+        });
+        if let (Some((_, h0)), Some(here)) = (before, heights[i]) {
+            let rise = here - h0;
+            if rise > 0.0 {
+                ascent += rise;
+            } else {
+                descent -= rise;
+            }
+        }
+        samples.push((distance, heights[i], slope));
+    }
+
+    ProfileResponse {
+        scene,
+        spacing,
+        length: positions.last().map_or(0.0, |&(d, _)| d),
+        ascent,
+        descent,
+        samples,
+    }
+}
+
+pub async fn get_profile(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<ProfileRequest>,
+) -> Result<Json<ProfileResponse>, ApiError> {
+    if req.points.len() < 2 {
+        return Err(ApiError::BadRequest(
+            "points must hold at least two [x, z] points".to_string(),
+        ));
+    }
+    if !req.points.iter().flatten().all(|c| c.is_finite()) {
+        return Err(ApiError::BadRequest(
+            "points must be finite numbers".to_string(),
+        ));
+    }
+    if let Some(spacing) = req.spacing
+        && !(spacing.is_finite() && spacing > 0.0)
+    {
+        return Err(ApiError::BadRequest(
+            "spacing must be a positive number".to_string(),
+        ));
+    }
+
+    let map = state.map(&name).await?;
+    tokio::task::spawn_blocking(move || {
+        let mosaic = map.mosaic()?;
+        let spacing = req.spacing.unwrap_or(mosaic.cell_size);
+        // In f64 so far-apart points can't overflow the count
+        let length: f64 = req
+            .points
+            .windows(2)
+            .map(|pair| {
+                let dx = pair[1][0] as f64 - pair[0][0] as f64;
+                let dz = pair[1][1] as f64 - pair[0][1] as f64;
+                dx.hypot(dz)
+            })
+            .sum();
+        // Sampling walks the route in f32
+        if length > f32::MAX as f64 {
+            return Err(ApiError::BadRequest(
+                "route is too long to sample".to_string(),
+            ));
+        }
+        let count = (length / spacing as f64).floor() + 2.0;
+        if count > MAX_PROFILE_SAMPLES as f64 {
+            return Err(ApiError::BadRequest(format!(
+                "profile would have {} samples, more than the limit of {}; use a larger spacing",
+                count, MAX_PROFILE_SAMPLES
+            )));
+        }
+
+        Ok(Json(profile(
+            map.data.scene.clone(),
+            mosaic,
+            &req.points,
+            spacing,
+        )))
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Profile task failed".to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{MapFile, TerrainChunk};
+
+    /// 5×5 mosaic ramping up from x = 0 to a crest of 2 at x = 2 and back down.
+    fn ramp() -> Mosaic {
+        let heights = (0..25usize)
+            .map(|i| 2.0 - (i % 5).abs_diff(2) as f32)
+            .collect();
+        Mosaic::new(&MapFile {
+            scene: "test".to_string(),
+            terrains: vec![TerrainChunk::from_heights("a", 0.0, 0.0, heights)],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn ramps_add_up_to_ascent_and_descent() {
+        let profile = profile("test".to_string(), &ramp(), &[[0.0, 2.0], [4.0, 2.0]], 0.5);
+        assert_eq!(profile.length, 4.0);
+        assert_eq!(profile.samples.len(), 9);
+        assert!((profile.ascent - 2.0).abs() < 1e-5);
+        assert!((profile.descent - 2.0).abs() < 1e-5);
+
+        let (_, height, slope) = profile.samples[1];
+        assert_eq!(height, Some(0.5));
+        assert!((slope.unwrap() - 45.0).abs() < 1e-3);
+        // Level across the crest, downhill past it
+        assert_eq!(profile.samples[4].2, Some(0.0));
+        assert!((profile.samples[7].2.unwrap() + 45.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn positions_turn_corners_and_end_on_the_last_point() {
+        let positions = positions(&[[0.0, 0.0], [1.5, 0.0], [1.5, 1.0]], 1.0);
+        assert_eq!(
+            positions,
+            [
+                (0.0, [0.0, 0.0]),
+                (1.0, [1.0, 0.0]),
+                (2.0, [1.5, 0.5]),
+                (2.5, [1.5, 1.0])
+            ]
+        );
+    }
+
+    #[test]
+    fn samples_off_the_terrain_are_left_out_of_the_totals() {
+        let profile = profile("test".to_string(), &ramp(), &[[0.0, 2.0], [8.0, 2.0]], 1.0);
+        assert_eq!(profile.samples[6], (6.0, None, None));
+        assert!((profile.ascent - 2.0).abs() < 1e-5);
+        assert!((profile.descent - 2.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,335 @@
+//! Ray and sight-line queries against a map's terrain.
+//!
+//! `POST /api/maps/{name}/raycast` finds where a ray first hits the ground
+//! and `POST /api/maps/{name}/los` whether one point can see another. Rays
+//! are clipped to each chunk's bounding box and marched through that
+//! chunk's heightmap at half its sample spacing; crossings are then refined
+//! by bisection. The terrain surface is the viewer's bilinear one.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{AppState, api::ApiError, map::MapFile};
+
+/// Bisection steps when refining a hit; enough for millimetre precision on
+/// any exported map.
+const REFINE_STEPS: usize = 24;
+
+/// Vertical slack around each chunk's bounding box.
+const BOX_PADDING: f32 = 1e-3;
+
+/// Distance from either end of a sight line within which the terrain is
+/// ignored, so points placed exactly on the ground can see each other.
+const SIGHT_CLEARANCE: f32 = 0.01;
+
+/// Where a ray meets the terrain.
+#[derive(Clone, Copy)]
+pub struct Hit {
+    /// Along the ray, in world units.
+    pub distance: f32,
+    pub point: [f32; 3],
+    /// Index into `terrains` of the chunk that was hit.
+    pub chunk: usize,
+}
+
+fn along(origin: [f32; 3], direction: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| origin[i] + direction[i] * t)
+}
+
+/// Parameter range in which the ray lies inside the box, if any.
+fn clip_to_box(
+    origin: [f32; 3],
+    direction: [f32; 3],
+    min: [f32; 3],
+    max: [f32; 3],
+) -> Option<(f32, f32)> {
+    let (mut t0, mut t1) = (0.0f32, f32::INFINITY);
+    for i in 0..3 {
+        if direction[i] == 0.0 {
+            if origin[i] < min[i] || origin[i] > max[i] {
+                return None;
+            }
+            continue;
+        }
+        let a = (min[i] - origin[i]) / direction[i];
+        let b = (max[i] - origin[i]) / direction[i];
+        t0 = t0.max(a.min(b));
+        t1 = t1.min(a.max(b));
+    }
+    (t0 <= t1).then_some((t0, t1))
+}
+
+impl MapFile {
+    /// First point at which the ray from `origin` along the unit vector
+    /// `direction` meets the terrain, between `min_distance` and
+    /// `max_distance` along it. A ray starting below the surface hits at
+    /// `min_distance`.
+    pub fn raycast(
+        &self,
+        origin: [f32; 3],
+        direction: [f32; 3],
+        min_distance: f32,
+        max_distance: f32,
+    ) -> Option<Hit> {
+        let mut best: Option<Hit> = None;
+        for (index, chunk) in self.terrains.iter().enumerate() {
+            // Cheap footprint test first; the height range needs a full scan
+            let footprint = clip_to_box(
+                origin,
+                [direction[0], 0.0, direction[2]],
+                [chunk.x, f32::NEG_INFINITY, chunk.z],
+                [chunk.x + chunk.width, f32::INFINITY, chunk.z + chunk.depth],
+            );
+            if footprint.is_none() {
+                continue;
+            }
+            // Padded so rays can dip below perfectly flat ground
+            let bounds = chunk.bounds();
+            let min = [bounds.min[0], bounds.min[1] - BOX_PADDING, bounds.min[2]];
+            let max = [bounds.max[0], bounds.max[1] + BOX_PADDING, bounds.max[2]];
+            let Some((t0, t1)) = clip_to_box(origin, direction, min, max) else {
+                continue;
+            };
+            let t0 = t0.max(min_distance);
+            let t1 = t1
+                .min(max_distance)
+                .min(best.map_or(f32::INFINITY, |b| b.distance));
+            if t0 > t1 {
+                continue;
+            }
+
+            let below = |t: f32| {
+                let [x, y, z] = along(origin, direction, t);
+                // Strictly, so lines grazing flat ground aren't blocked
+                y < chunk.height_at(x, z)
+            };
+            let last = (chunk.resolution - 1) as f32;
+            let step = chunk.width.min(chunk.depth) / last / 2.0;
+            let steps = ((t1 - t0) / step).ceil() as usize;
+            let mut previous = t0;
+            for k in 0..=steps {
+                // Counted from where the ray enters the chunk, so rounding far
+                // from the origin can't stall the march
+                let t = if k == steps { t1 } else { t0 + k as f32 * step };
+                if k > 0 && t <= previous {
+                    break;
+                }
+                if below(t) {
+                    // Narrow down between the last sample above and this one
+                    let (mut above, mut under) = (previous, t);
+                    if k > 0 {
+                        for _ in 0..REFINE_STEPS {
+                            let middle = (above + under) / 2.0;
+                            if below(middle) {
+                                under = middle;
+                            } else {
+                                above = middle;
+                            }
+                        }
+                    }
+                    let [x, _, z] = along(origin, direction, under);
+                    // Where chunks overlap, the first one in the file is the
+                    // terrain, as in `chunk_at`
+                    if self
+                        .chunk_at(x, z)
+                        .is_none_or(|owner| std::ptr::eq(owner, chunk))
+                    {
+                        best = Some(Hit {
+                            distance: under,
+                            point: [x, chunk.height_at(x, z), z],
+                            chunk: index,
+                        });
+                        break;
+                    }
+                }
+                previous = t;
+            }
+        }
+        best
+    }
+}
+
+fn parse_direction(direction: [f32; 3]) -> Result<[f32; 3], ApiError> {
+    let length = direction.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if !(length.is_finite() && length > 0.0) {
+        return Err(ApiError::BadRequest(
+            "direction must be a non-zero vector".to_string(),
+        ));
+    }
+    Ok(direction.map(|c| c / length))
+}
+
+fn check_point(name: &str, point: [f32; 3]) -> Result<(), ApiError> {
+    if point.iter().all(|c| c.is_finite()) {
+        Ok(())
+    } else {
+        Err(ApiError::BadRequest(format!(
+            "{} must be three finite numbers",
+            name
+        )))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RaycastRequest {
+    /// `[x, y, z]` the ray starts at.
+    origin: [f32; 3],
+    /// `[x, y, z]`, normalised by the server.
+    direction: [f32; 3],
+    /// Unlimited by default.
+    max_distance: Option<f32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RaycastResponse {
+    hit: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    point: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<String>,
+}
+
+pub async fn raycast(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<RaycastRequest>,
+) -> Result<Json<RaycastResponse>, ApiError> {
+    check_point("origin", req.origin)?;
+    let direction = parse_direction(req.direction)?;
+    let max_distance = req.max_distance.unwrap_or(f32::INFINITY);
+    if max_distance.is_nan() || max_distance <= 0.0 {
+        return Err(ApiError::BadRequest(
+            "maxDistance must be a positive number".to_string(),
+        ));
+    }
+
+    let map = state.map(&name).await?;
+    tokio::task::spawn_blocking(move || {
+        let hit = map.data.raycast(req.origin, direction, 0.0, max_distance);
+        Json(RaycastResponse {
+            hit: hit.is_some(),
+            point: hit.map(|h| h.point),
+            distance: hit.map(|h| h.distance),
+            chunk: hit.map(|h| map.data.terrains[h.chunk].name.clone()),
+        })
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Raycast failed".to_string()))
+}
+
+#[derive(Deserialize)]
+pub struct SightRequest {
+    /// `[x, y, z]` of the observer.
+    from: [f32; 3],
+    /// `[x, y, z]` of the target.
+    to: [f32; 3],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SightResponse {
+    visible: bool,
+    /// Between the two points.
+    distance: f32,
+    /// First terrain point in the way, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocking_point: Option<[f32; 3]>,
+    /// From `from` to the blocking point.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocking_distance: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<String>,
+}
+
+pub async fn line_of_sight(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<SightRequest>,
+) -> Result<Json<SightResponse>, ApiError> {
+    check_point("from", req.from)?;
+    check_point("to", req.to)?;
+    let delta = [0, 1, 2].map(|i| req.to[i] - req.from[i]);
+    let distance = delta.iter().map(|c| c * c).sum::<f32>().sqrt();
+
+    let map = state.map(&name).await?;
+    tokio::task::spawn_blocking(move || {
+        let hit = if distance > 2.0 * SIGHT_CLEARANCE {
+            let direction = delta.map(|c| c / distance);
+            map.data.raycast(
+                req.from,
+                direction,
+                SIGHT_CLEARANCE,
+                distance - SIGHT_CLEARANCE,
+            )
+        } else {
+            None
+        };
+        Json(SightResponse {
+            visible: hit.is_none(),
+            distance,
+            blocking_point: hit.map(|h| h.point),
+            blocking_distance: hit.map(|h| h.distance),
+            chunk: hit.map(|h| map.data.terrains[h.chunk].name.clone()),
+        })
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Line of sight check failed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TerrainChunk;
+
+    /// 4×4 world units of flat ground at height 2.
+    fn flat() -> MapFile {
+        MapFile {
+            scene: "test".to_string(),
+            terrains: vec![TerrainChunk::from_heights("a", 0.0, 0.0, vec![2.0; 25])],
+        }
+    }
+
+    #[test]
+    fn straight_down_hits_at_the_height_difference() {
+        let hit = flat()
+            .raycast([1.0, 5.0, 1.0], [0.0, -1.0, 0.0], 0.0, f32::INFINITY)
+            .unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-4);
+        assert_eq!(hit.point, [1.0, 2.0, 1.0]);
+        assert_eq!(hit.chunk, 0);
+    }
+
+    #[test]
+    fn bisection_finds_a_shallow_hit_between_march_steps() {
+        // Drops 1 over 10 units, so the march samples straddle the hit
+        let direction = parse_direction([10.0, -1.0, 0.0]).unwrap();
+        let hit = flat()
+            .raycast([0.3, 2.3, 1.5], direction, 0.0, f32::INFINITY)
+            .unwrap();
+        let expected = 0.3 * 101f32.sqrt();
+        assert!((hit.distance - expected).abs() < 1e-3);
+        assert!((hit.point[0] - 3.3).abs() < 1e-3);
+        assert_eq!(hit.point[1], 2.0);
+    }
+
+    #[test]
+    fn rays_miss_beyond_max_distance_and_going_up() {
+        let map = flat();
+        assert!(
+            map.raycast([1.0, 5.0, 1.0], [0.0, -1.0, 0.0], 0.0, 2.5)
+                .is_none()
+        );
+        assert!(
+            map.raycast([1.0, 5.0, 1.0], [0.0, 1.0, 0.0], 0.0, f32::INFINITY)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{MapFile, TerrainChunk},
+};
+
+impl TerrainChunk {
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        x >= self.x && x <= self.x + self.width && z >= self.z && z <= self.z + self.depth
+    }
+
+    /// Height sample at grid position `(col, row)`, relative to the chunk's `y`.
+    pub fn sample(&self, col: usize, row: usize) -> f32 {
+        self.height_map[row * self.resolution as usize + col]
+    }
+
+    /// Bilinearly interpolated sample at fractional grid position `(u, v)`,
+    /// relative to the chunk's `y`. Positions outside the grid are clamped.
+    pub fn sample_bilinear(&self, u: f32, v: f32) -> f32 {
+        let last = (self.resolution - 1) as f32;
+        let u = u.clamp(0.0, last);
+        let v = v.clamp(0.0, last);
+
+        let col0 = u.floor() as usize;
+        let row0 = v.floor() as usize;
+        let col1 = (col0 + 1).min(self.resolution as usize - 1);
+        let row1 = (row0 + 1).min(self.resolution as usize - 1);
+        let fu = u - col0 as f32;
+        let fv = v - row0 as f32;
+
+        let top = self.sample(col0, row0) * (1.0 - fu) + self.sample(col1, row0) * fu;
+        let bottom = self.sample(col0, row1) * (1.0 - fu) + self.sample(col1, row1) * fu;
+        top * (1.0 - fv) + bottom * fv
+    }
+
+    /// Bilinearly interpolated world height at `(x, z)`. Positions outside the
+    /// chunk are clamped to its border.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let last = (self.resolution - 1) as f32;
+        let u = (x - self.x) / self.width * last;
+        let v = (z - self.z) / self.depth * last;
+        self.y + self.sample_bilinear(u, v)
+    }
+}
+
+impl MapFile {
+    pub fn chunk_at(&self, x: f32, z: f32) -> Option<&TerrainChunk> {
+        self.terrains.iter().find(|chunk| chunk.contains(x, z))
+    }
+
+    /// World height at `(x, z)`, or `None` if no terrain covers that position.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        self.chunk_at(x, z).map(|chunk| chunk.height_at(x, z))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HeightQuery {
+    x: f32,
+    z: f32,
+}
+
+#[derive(Serialize)]
+pub struct HeightResponse {
+    x: f32,
+    z: f32,
+    y: Option<f32>,
+    chunk: Option<String>,
+}
+
+pub async fn get_height(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<HeightQuery>,
+) -> Result<Json<HeightResponse>, ApiError> {
+    let map = state.map(&name).await?;
+    let chunk = map.data.chunk_at(query.x, query.z);
+    Ok(Json(HeightResponse {
+        x: query.x,
+        z: query.z,
+        y: chunk.map(|c| c.height_at(query.x, query.z)),
+        chunk: chunk.map(|c| c.name.clone()),
+    }))
+}
@@ -0,0 +1,385 @@
+//! Shared borders between neighbouring chunks.
+//!
+//! Unity keeps the edges of connected terrains in sync, but exports from
+//! scenes where the terrains were edited separately can disagree along a
+//! border, which shows up as cracks in the viewer. This finds every pair of
+//! chunks that touch along an edge, measures how far apart their heights are
+//! there, and can produce a copy of the map with the borders made to agree.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{MapFile, TerrainChunk},
+    spatial::ChunkIndex,
+};
+
+/// How far apart, in world units, two chunk edges may be and still count as
+/// touching. Exported coordinates are rounded floats.
+const EDGE_TOLERANCE: f32 = 0.01;
+
+/// Mismatch in world units above which a seam is reported as cracked.
+const DEFAULT_THRESHOLD: f32 = 0.001;
+
+/// The line a seam lies on: constant `x` (chunks side by side along X) or
+/// constant `z`.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Z,
+}
+
+/// A border shared by chunk `a` and chunk `b`, with `a` on the low side.
+/// The seam runs from `from` to `to` along the other axis.
+#[derive(Clone, Copy, Debug)]
+pub struct Seam {
+    pub a: usize,
+    pub b: usize,
+    pub axis: Axis,
+    pub position: f32,
+    pub from: f32,
+    pub to: f32,
+}
+
+/// Height differences measured along a seam.
+#[derive(Clone, Copy, Debug)]
+pub struct Mismatch {
+    pub samples: usize,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl TerrainChunk {
+    /// World XZ position of grid vertex `(col, row)`.
+    pub fn vertex_position(&self, col: usize, row: usize) -> (f32, f32) {
+        let last = (self.resolution - 1) as f32;
+        (
+            self.x + col as f32 / last * self.width,
+            self.z + row as f32 / last * self.depth,
+        )
+    }
+
+    /// Distance between neighbouring vertices along the given seam axis, i.e.
+    /// along Z for a seam of constant `x`.
+    fn spacing_along(&self, axis: Axis) -> f32 {
+        let last = (self.resolution - 1) as f32;
+        match axis {
+            Axis::X => self.depth / last,
+            Axis::Z => self.width / last,
+        }
+    }
+}
+
+impl Seam {
+    fn point(&self, t: f32) -> (f32, f32) {
+        match self.axis {
+            Axis::X => (self.position, t),
+            Axis::Z => (t, self.position),
+        }
+    }
+
+    /// Compares world heights of both chunks along the seam, at the spacing of
+    /// the finer of the two grids.
+    pub fn mismatch(&self, map: &MapFile) -> Mismatch {
+        let a = &map.terrains[self.a];
+        let b = &map.terrains[self.b];
+        let spacing = a.spacing_along(self.axis).min(b.spacing_along(self.axis));
+        let samples = (((self.to - self.from) / spacing).round() as usize + 1).max(2);
+
+        let mut max = 0.0f32;
+        let mut sum = 0.0f64;
+        for k in 0..samples {
+            let t = self.from + (self.to - self.from) * k as f32 / (samples - 1) as f32;
+            let (x, z) = self.point(t);
+            let diff = (a.height_at(x, z) - b.height_at(x, z)).abs();
+            max = max.max(diff);
+            sum += diff as f64;
+        }
+        Mismatch {
+            samples,
+            max,
+            mean: (sum / samples as f64) as f32,
+        }
+    }
+}
+
+/// Every pair of chunks sharing a stretch of edge, ordered by `a` then `b`.
+/// Chunks that only meet at a corner are not included.
+pub fn find_seams(map: &MapFile, index: &ChunkIndex) -> Vec<Seam> {
+    let mut seams = Vec::new();
+    for (i, a) in map.terrains.iter().enumerate() {
+        let min = [a.x - EDGE_TOLERANCE, a.z - EDGE_TOLERANCE];
+        let max = [
+            a.x + a.width + EDGE_TOLERANCE,
+            a.z + a.depth + EDGE_TOLERANCE,
+        ];
+        for j in index.intersecting(min, max) {
+            let b = &map.terrains[j];
+            if j == i {
+                continue;
+            }
+            if ((a.x + a.width) - b.x).abs() <= EDGE_TOLERANCE {
+                let from = a.z.max(b.z);
+                let to = (a.z + a.depth).min(b.z + b.depth);
+                if to - from > EDGE_TOLERANCE {
+                    seams.push(Seam {
+                        a: i,
+                        b: j,
+                        axis: Axis::X,
+                        position: b.x,
+                        from,
+                        to,
+                    });
+                }
+            }
+            if ((a.z + a.depth) - b.z).abs() <= EDGE_TOLERANCE {
+                let from = a.x.max(b.x);
+                let to = (a.x + a.width).min(b.x + b.width);
+                if to - from > EDGE_TOLERANCE {
+                    seams.push(Seam {
+                        a: i,
+                        b: j,
+                        axis: Axis::Z,
+                        position: b.z,
+                        from,
+                        to,
+                    });
+                }
+            }
+        }
+    }
+    seams
+}
+
+/// Grid positions on the outer ring of a `res × res` grid, each once.
+fn border_vertices(res: usize) -> impl Iterator<Item = (usize, usize)> {
+    let last = res - 1;
+    let rows = (0..res).flat_map(move |col| [(col, 0), (col, last)]);
+    let cols = (1..last).flat_map(move |row| [(0, row), (last, row)]);
+    rows.chain(cols)
+}
+
+/// Copy of `map` in which chunks agree on every shared border.
+///
+/// Each border vertex first takes the mean world height of all chunks
+/// covering its position, which closes seams between grids of the same
+/// resolution, corners included. Where a neighbour has a coarser grid along
+/// the edge, the finer chunk's vertices are then moved onto the coarse edge so
+/// the extra vertices don't open T-junction cracks.
+pub fn stitch(map: &MapFile, index: &ChunkIndex) -> MapFile {
+    let covering = |x: f32, z: f32| {
+        let min = [x - EDGE_TOLERANCE, z - EDGE_TOLERANCE];
+        let max = [x + EDGE_TOLERANCE, z + EDGE_TOLERANCE];
+        index.intersecting(min, max)
+    };
+
+    let mut averaged = map.clone();
+    for (i, chunk) in map.terrains.iter().enumerate() {
+        let res = chunk.resolution as usize;
+        for (col, row) in border_vertices(res) {
+            let (x, z) = chunk.vertex_position(col, row);
+            let others = covering(x, z);
+            if others.len() < 2 {
+                continue;
+            }
+            let sum: f32 = others
+                .iter()
+                .map(|&j| map.terrains[j].height_at(x, z))
+                .sum();
+            averaged.terrains[i].height_map[row * res + col] = sum / others.len() as f32 - chunk.y;
+        }
+    }
+
+    let mut stitched = averaged.clone();
+    for (i, chunk) in averaged.terrains.iter().enumerate() {
+        let res = chunk.resolution as usize;
+        let last = res - 1;
+        for (col, row) in border_vertices(res) {
+            // Corners are vertices of every grid that meets there
+            let axis = match (col == 0 || col == last, row == 0 || row == last) {
+                (true, false) => Axis::X,
+                (false, true) => Axis::Z,
+                _ => continue,
+            };
+            let (x, z) = chunk.vertex_position(col, row);
+            let spacing = chunk.spacing_along(axis);
+            let coarsest = covering(x, z)
+                .into_iter()
+                .map(|j| &averaged.terrains[j])
+                .filter(|other| other.spacing_along(axis) > spacing + f32::EPSILON)
+                .max_by(|p, q| p.spacing_along(axis).total_cmp(&q.spacing_along(axis)));
+            if let Some(coarse) = coarsest {
+                stitched.terrains[i].height_map[row * res + col] = coarse.height_at(x, z) - chunk.y;
+            }
+        }
+    }
+    stitched
+}
+
+#[derive(Deserialize)]
+pub struct SeamQuery {
+    /// Seams whose largest mismatch exceeds this many world units are
+    /// reported as cracked.
+    threshold: Option<f32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeamInfo {
+    a: String,
+    b: String,
+    axis: Axis,
+    position: f32,
+    from: f32,
+    to: f32,
+    samples: usize,
+    max_mismatch: f32,
+    mean_mismatch: f32,
+    cracked: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeamReport {
+    scene: String,
+    file: String,
+    threshold: f32,
+    seam_count: usize,
+    cracked_count: usize,
+    max_mismatch: f32,
+    seams: Vec<SeamInfo>,
+}
+
+pub async fn get_seams(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<SeamQuery>,
+) -> Result<Json<SeamReport>, ApiError> {
+    let threshold = query.threshold.unwrap_or(DEFAULT_THRESHOLD);
+    if !(threshold.is_finite() && threshold >= 0.0) {
+        return Err(ApiError::BadRequest(
+            "threshold must be a non-negative number".to_string(),
+        ));
+    }
+
+    let map = state.map(&name).await?;
+    let seams: Vec<SeamInfo> = find_seams(&map.data, &map.index)
+        .iter()
+        .map(|seam| {
+            let mismatch = seam.mismatch(&map.data);
+            SeamInfo {
+                a: map.data.terrains[seam.a].name.clone(),
+                b: map.data.terrains[seam.b].name.clone(),
+                axis: seam.axis,
+                position: seam.position,
+                from: seam.from,
+                to: seam.to,
+                samples: mismatch.samples,
+                max_mismatch: mismatch.max,
+                mean_mismatch: mismatch.mean,
+                cracked: mismatch.max > threshold,
+            }
+        })
+        .collect();
+
+    Ok(Json(SeamReport {
+        scene: map.data.scene.clone(),
+        file: map.file_name.clone(),
+        threshold,
+        seam_count: seams.len(),
+        cracked_count: seams.iter().filter(|s| s.cracked).count(),
+        max_mismatch: seams.iter().map(|s| s.max_mismatch).fold(0.0, f32::max),
+        seams,
+    }))
+}
+
+/// The map with stitched heightmaps, in the same shape as the map file so the
+/// viewer can load it in place of `/maps/{file}`.
+pub async fn get_stitched(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<MapFile>, ApiError> {
+    let map = state.map(&name).await?;
+    tokio::task::spawn_blocking(move || Json(stitch(&map.data, &map.index)))
+        .await
+        .map_err(|_| ApiError::Internal("Stitching failed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(terrains: Vec<TerrainChunk>) -> MapFile {
+        MapFile {
+            scene: "test".to_string(),
+            terrains,
+        }
+    }
+
+    #[test]
+    fn stitch_closes_a_cracked_edge() {
+        // Side by side along X, with the shared column at 1 on one side and
+        // 3 on the other
+        #[rustfmt::skip]
+        let map = map(vec![
+            TerrainChunk::from_heights("a", 0.0, 0.0, vec![
+                0.0, 0.0, 1.0,
+                0.0, 0.0, 1.0,
+                0.0, 0.0, 1.0,
+            ]),
+            TerrainChunk::from_heights("b", 2.0, 0.0, vec![
+                3.0, 5.0, 5.0,
+                3.0, 5.0, 5.0,
+                3.0, 5.0, 5.0,
+            ]),
+        ]);
+        let index = ChunkIndex::new(&map);
+        let seams = find_seams(&map, &index);
+        assert_eq!(seams.len(), 1);
+        let seam = &seams[0];
+        assert_eq!((seam.a, seam.b, seam.axis), (0, 1, Axis::X));
+        assert_eq!(seam.mismatch(&map).max, 2.0);
+
+        let stitched = stitch(&map, &index);
+        assert_eq!(seam.mismatch(&stitched).max, 0.0);
+        for row in 0..3 {
+            assert_eq!(stitched.terrains[0].sample(2, row), 2.0);
+            assert_eq!(stitched.terrains[1].sample(0, row), 2.0);
+            // Only the border moves
+            assert_eq!(stitched.terrains[0].sample(1, row), 0.0);
+            assert_eq!(stitched.terrains[1].sample(1, row), 5.0);
+        }
+    }
+
+    #[test]
+    fn stitch_lays_finer_edges_onto_coarser_ones() {
+        // The fine chunk's middle border sample has no counterpart on the
+        // coarse chunk, whose edge runs straight from 0 to 4
+        #[rustfmt::skip]
+        let fine = TerrainChunk::from_heights("fine", 0.0, 0.0, vec![
+            0.0, 0.0, 0.0,
+            0.0, 0.0, 9.0,
+            0.0, 0.0, 4.0,
+        ]);
+        let coarse = TerrainChunk {
+            width: 2.0,
+            depth: 2.0,
+            ..TerrainChunk::from_heights("coarse", 2.0, 0.0, vec![0.0, 0.0, 4.0, 4.0])
+        };
+        let map = map(vec![fine, coarse]);
+        let stitched = stitch(&map, &ChunkIndex::new(&map));
+
+        let edge: Vec<f32> = (0..3)
+            .map(|row| stitched.terrains[0].sample(2, row))
+            .collect();
+        assert_eq!(edge, [0.0, 2.0, 4.0]);
+        assert_eq!(stitched.terrains[1].height_map, [0.0, 0.0, 4.0, 4.0]);
+    }
+}
@@ -0,0 +1,103 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+};
+use rstar::{AABB, RTree, RTreeObject};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    AppState,
+    api::ApiError,
+    map::{MapFile, TerrainChunk},
+};
+
+/// Footprint of one chunk on the XZ plane, pointing back into `terrains`.
+struct ChunkFootprint {
+    index: usize,
+    envelope: AABB<[f32; 2]>,
+}
+
+impl RTreeObject for ChunkFootprint {
+    type Envelope = AABB<[f32; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+/// R-tree over the XZ footprints of a map's chunks.
+pub struct ChunkIndex {
+    tree: RTree<ChunkFootprint>,
+}
+
+impl ChunkIndex {
+    pub fn new(map: &MapFile) -> Self {
+        let footprints = map
+            .terrains
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| ChunkFootprint {
+                index,
+                envelope: AABB::from_corners(
+                    [chunk.x, chunk.z],
+                    [chunk.x + chunk.width, chunk.z + chunk.depth],
+                ),
+            })
+            .collect();
+        ChunkIndex {
+            tree: RTree::bulk_load(footprints),
+        }
+    }
+
+    /// Indices into `terrains` of every chunk touching the given XZ box, in
+    /// file order.
+    pub fn intersecting(&self, min: [f32; 2], max: [f32; 2]) -> Vec<usize> {
+        let mut indices: Vec<usize> = self
+            .tree
+            .locate_in_envelope_intersecting(AABB::from_corners(min, max))
+            .map(|footprint| footprint.index)
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoundsQuery {
+    min_x: f32,
+    min_z: f32,
+    max_x: f32,
+    max_z: f32,
+}
+
+/// Same shape as a map file, so clients can feed it to their map loader.
+#[derive(Serialize)]
+pub struct ChunksResponse<'a> {
+    scene: &'a str,
+    terrains: Vec<&'a TerrainChunk>,
+}
+
+pub async fn get_chunks(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<BoundsQuery>,
+) -> Result<Response, ApiError> {
+    let map = state.map(&name).await?;
+    if !(query.min_x <= query.max_x && query.min_z <= query.max_z) {
+        return Err(ApiError::BadRequest(
+            "minX/minZ must not exceed maxX/maxZ".to_string(),
+        ));
+    }
+
+    let indices = map
+        .index
+        .intersecting([query.min_x, query.min_z], [query.max_x, query.max_z]);
+    let response = ChunksResponse {
+        scene: &map.data.scene,
+        terrains: indices.iter().map(|&i| &map.data.terrains[i]).collect(),
+    };
+    Ok(Json(response).into_response())
+}
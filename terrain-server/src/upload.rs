@@ -0,0 +1,121 @@
+//! Publishing maps over HTTP. `PUT /api/maps/{name}` uploads or replaces a
+//! map and `DELETE` removes it; both require the configured API token as a
+//! bearer token. Uploads are validated like maps loaded from disk before
+//! anything is written, and every change is broadcast like a hot reload.
+//! Heightmap imports (see `import`) are stored the same way.
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use std::{fs, sync::Arc};
+
+use crate::{
+    AppState,
+    api::ApiError,
+    info::MapInfo,
+    map::{LoadedMap, MapFile, map_key},
+    validate::write_atomically,
+    watch,
+};
+
+/// Largest accepted upload; the biggest exported maps are a few megabytes.
+pub const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+pub fn authorize(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(expected) = &state.api_token else {
+        return Err(ApiError::Forbidden(
+            "Map uploads are disabled; set api_token to enable them".to_string(),
+        ));
+    };
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match provided {
+        Some(token) if constant_time_eq(token.trim().as_bytes(), expected.as_bytes()) => Ok(()),
+        Some(_) => Err(ApiError::Unauthorized("Invalid API token".to_string())),
+        None => Err(ApiError::Unauthorized("Missing bearer token".to_string())),
+    }
+}
+
+/// Compares without returning early, so response times don't reveal how much
+/// of a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `ps0_10_cave4` or `ps0_10_cave4.json` → `ps0_10_cave4.json`. Only plain
+/// names are accepted, so uploads can't escape `maps_dir` or collide with
+/// sidecar and temporary files.
+pub fn file_name(name: &str) -> Result<String, ApiError> {
+    let key = map_key(name);
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(ApiError::BadRequest(
+            "Map names may only contain letters, digits, '_' and '-'".to_string(),
+        ));
+    }
+    Ok(format!("{}.json", key))
+}
+
+pub async fn put_map(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    authorize(&state, &headers)?;
+    let file_name = file_name(&name)?;
+    MapFile::from_slice(&body).map_err(|e| ApiError::BadRequest(format!("Invalid map: {}", e)))?;
+    store(&state, file_name, body).await
+}
+
+/// Writes an already validated map to `maps_dir` and publishes it. Answers
+/// `201 Created` for new maps and `200 OK` for replaced ones, with the map's
+/// info as the body.
+pub async fn store(state: &AppState, file_name: String, json: Bytes) -> Result<Response, ApiError> {
+    let existed = state.maps.read().await.get(&file_name).is_some();
+    let path = state.maps_dir.join(&file_name);
+    let name = file_name.clone();
+    let loaded = tokio::task::spawn_blocking(move || {
+        write_atomically(&path, &json)?;
+        Ok::<_, std::io::Error>(LoadedMap::load(&path, name))
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Upload task failed".to_string()))?
+    .map_err(|e| ApiError::Internal(format!("Could not write {}: {}", file_name, e)))?;
+
+    watch::publish(state, file_name.clone(), Some(loaded)).await;
+    let map = state.map(&file_name).await?;
+    let status = if existed {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok((status, Json(MapInfo::new(&map))).into_response())
+}
+
+pub async fn delete_map(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, &headers)?;
+    let file_name = file_name(&name)?;
+    let path = state.maps_dir.join(&file_name);
+    if !path.is_file() {
+        return Err(ApiError::map_not_found(&name));
+    }
+    fs::remove_file(&path)
+        .map_err(|e| ApiError::Internal(format!("Could not delete {}: {}", file_name, e)))?;
+
+    watch::publish(&state, file_name, None).await;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,401 @@
+//! `terrain-server validate`: offline checks for a directory of exported maps.
+//!
+//! Unlike the startup validation in `map.rs`, which stops at the first
+//! problem, this reports everything wrong with every file and can rewrite the
+//! ones whose problems have an unambiguous repair.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{
+    map::{MapFile, TerrainChunk},
+    spatial::ChunkIndex,
+};
+
+/// Overlaps smaller than this fraction of the smaller chunk's area are treated
+/// as float noise on a shared edge.
+const OVERLAP_TOLERANCE: f32 = 1e-4;
+
+#[derive(Debug)]
+pub enum Issue {
+    NoTerrains,
+    NonFiniteField(&'static str),
+    NonPositiveExtent {
+        field: &'static str,
+        value: f32,
+    },
+    ResolutionTooSmall(u32),
+    /// `square_of` is set when the length is the square of another resolution,
+    /// in which case the `resolution` field is what's wrong.
+    HeightMapLength {
+        expected: usize,
+        actual: usize,
+        square_of: Option<u32>,
+    },
+    NonFiniteHeights {
+        count: usize,
+        first: usize,
+    },
+    HeightsOutOfRange {
+        count: usize,
+        worst: f32,
+    },
+    DuplicateName,
+    Overlap {
+        other: String,
+        area: f32,
+    },
+}
+
+impl Issue {
+    pub fn fixable(&self) -> bool {
+        match self {
+            Issue::HeightMapLength { square_of, .. } => square_of.is_some(),
+            Issue::NonFiniteHeights { .. }
+            | Issue::HeightsOutOfRange { .. }
+            | Issue::DuplicateName => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::NoTerrains => write!(f, "map has no terrains"),
+            Issue::NonFiniteField(field) => write!(f, "{} is not a finite number", field),
+            Issue::NonPositiveExtent { field, value } => {
+                write!(f, "{} must be positive, got {}", field, value)
+            }
+            Issue::ResolutionTooSmall(res) => write!(f, "resolution {} is smaller than 2", res),
+            Issue::HeightMapLength {
+                expected, actual, ..
+            } => write!(
+                f,
+                "heightMap has {} samples, expected resolution² = {}",
+                actual, expected
+            ),
+            Issue::NonFiniteHeights { count, first } => write!(
+                f,
+                "{} heightMap values are NaN or infinite (first at index {})",
+                count, first
+            ),
+            Issue::HeightsOutOfRange { count, worst } => write!(
+                f,
+                "{} heights lie outside ±maxHeight (worst {})",
+                count, worst
+            ),
+            Issue::DuplicateName => write!(f, "name is used by an earlier chunk"),
+            Issue::Overlap { other, area } => {
+                write!(f, "overlaps {} by {:.3} square units", other, area)
+            }
+        }
+    }
+}
+
+/// An issue, and the chunk it concerns if it isn't map-wide.
+pub struct Finding {
+    pub chunk: Option<String>,
+    pub issue: Issue,
+}
+
+fn check_chunk(chunk: &TerrainChunk) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (field, value) in [
+        ("x", chunk.x),
+        ("y", chunk.y),
+        ("z", chunk.z),
+        ("width", chunk.width),
+        ("depth", chunk.depth),
+        ("maxHeight", chunk.max_height),
+    ] {
+        if !value.is_finite() {
+            issues.push(Issue::NonFiniteField(field));
+        }
+    }
+    for (field, value) in [
+        ("width", chunk.width),
+        ("depth", chunk.depth),
+        ("maxHeight", chunk.max_height),
+    ] {
+        if value <= 0.0 {
+            issues.push(Issue::NonPositiveExtent { field, value });
+        }
+    }
+    if chunk.resolution < 2 {
+        issues.push(Issue::ResolutionTooSmall(chunk.resolution));
+    }
+
+    let expected = chunk.resolution as usize * chunk.resolution as usize;
+    let actual = chunk.height_map.len();
+    if actual != expected {
+        let root = (actual as f64).sqrt().round() as usize;
+        issues.push(Issue::HeightMapLength {
+            expected,
+            actual,
+            square_of: (root >= 2 && root * root == actual).then_some(root as u32),
+        });
+    }
+
+    let non_finite: Vec<usize> = chunk
+        .height_map
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| !h.is_finite())
+        .map(|(i, _)| i)
+        .collect();
+    if let Some(&first) = non_finite.first() {
+        issues.push(Issue::NonFiniteHeights {
+            count: non_finite.len(),
+            first,
+        });
+    }
+
+    // Unity stores signed 16-bit samples scaled by maxHeight
+    if chunk.max_height.is_finite() && chunk.max_height > 0.0 {
+        let out_of_range: Vec<f32> = chunk
+            .height_map
+            .iter()
+            .copied()
+            .filter(|h| h.is_finite() && h.abs() > chunk.max_height)
+            .collect();
+        if !out_of_range.is_empty() {
+            let worst = out_of_range
+                .iter()
+                .copied()
+                .fold(0.0f32, |w, h| if h.abs() > w.abs() { h } else { w });
+            issues.push(Issue::HeightsOutOfRange {
+                count: out_of_range.len(),
+                worst,
+            });
+        }
+    }
+    issues
+}
+
+pub fn check(map: &MapFile) -> Vec<Finding> {
+    if map.terrains.is_empty() {
+        return vec![Finding {
+            chunk: None,
+            issue: Issue::NoTerrains,
+        }];
+    }
+
+    let mut findings = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (i, chunk) in map.terrains.iter().enumerate() {
+        for issue in check_chunk(chunk) {
+            findings.push(Finding {
+                chunk: Some(chunk.name.clone()),
+                issue,
+            });
+        }
+        if seen.insert(&chunk.name, i).is_some() {
+            findings.push(Finding {
+                chunk: Some(chunk.name.clone()),
+                issue: Issue::DuplicateName,
+            });
+        }
+    }
+
+    // Only footprints with sane extents can be compared
+    let sane = map
+        .terrains
+        .iter()
+        .all(|c| [c.x, c.z, c.width, c.depth].iter().all(|v| v.is_finite()));
+    if sane {
+        let index = ChunkIndex::new(map);
+        for (i, a) in map.terrains.iter().enumerate() {
+            let min = [a.x, a.z];
+            let max = [a.x + a.width, a.z + a.depth];
+            for j in index.intersecting(min, max) {
+                // Report each pair once
+                if j <= i {
+                    continue;
+                }
+                let b = &map.terrains[j];
+                let w = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+                let d = (a.z + a.depth).min(b.z + b.depth) - a.z.max(b.z);
+                let area = w.max(0.0) * d.max(0.0);
+                let smaller = (a.width * a.depth).min(b.width * b.depth);
+                if area > smaller * OVERLAP_TOLERANCE {
+                    findings.push(Finding {
+                        chunk: Some(a.name.clone()),
+                        issue: Issue::Overlap {
+                            other: b.name.clone(),
+                            area,
+                        },
+                    });
+                }
+            }
+        }
+    }
+    findings
+}
+
+/// Repairs everything `Issue::fixable` covers. Returns whether anything changed.
+pub fn fix(map: &mut MapFile) -> bool {
+    let mut changed = false;
+    let mut taken: HashSet<String> = map.terrains.iter().map(|c| c.name.clone()).collect();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for chunk in &mut map.terrains {
+        let len = chunk.height_map.len();
+        let root = (len as f64).sqrt().round() as usize;
+        if len != chunk.resolution as usize * chunk.resolution as usize
+            && root >= 2
+            && root * root == len
+        {
+            chunk.resolution = root as u32;
+            changed = true;
+        }
+
+        let limit = chunk.max_height;
+        if limit.is_finite() && limit > 0.0 {
+            for h in &mut chunk.height_map {
+                if !h.is_finite() {
+                    // Unknown samples sit at the chunk's base height
+                    *h = 0.0;
+                    changed = true;
+                } else if h.abs() > limit {
+                    *h = h.clamp(-limit, limit);
+                    changed = true;
+                }
+            }
+        }
+
+        // Rename later duplicates to the first free Name_2, Name_3, ...
+        if !seen.insert(chunk.name.clone()) {
+            let mut suffix = 2;
+            while taken.contains(&format!("{}_{}", chunk.name, suffix)) {
+                suffix += 1;
+            }
+            chunk.name = format!("{}_{}", chunk.name, suffix);
+            taken.insert(chunk.name.clone());
+            seen.insert(chunk.name.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Writes next to the target and renames over it, so readers such as the
+/// server's file watcher never see a half-written map.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("map");
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+fn map_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(path)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn print_findings(findings: &[Finding]) {
+    for finding in findings {
+        let fixable = if finding.issue.fixable() {
+            " [fixable]"
+        } else {
+            ""
+        };
+        match &finding.chunk {
+            Some(chunk) => println!("    {}: {}{}", chunk, finding.issue, fixable),
+            None => println!("    {}{}", finding.issue, fixable),
+        }
+    }
+}
+
+/// Checks every map in `path` (a directory or a single file) and returns a
+/// failing exit code if any map still has problems.
+pub fn run(path: &Path, apply_fixes: bool) -> ExitCode {
+    let files = match map_files(path) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error reading {:?}: {}", path, e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut failed = 0;
+    let mut fixed = 0;
+    for file in &files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        let parsed = fs::read(file)
+            .map_err(|e| format!("could not read file: {}", e))
+            .and_then(|bytes| {
+                serde_json::from_slice::<MapFile>(&bytes)
+                    .map_err(|e| format!("invalid JSON: {}", e))
+            });
+        let mut map = match parsed {
+            Ok(map) => map,
+            Err(e) => {
+                println!("FAIL  {}\n    {}", name, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let findings = check(&map);
+        if findings.is_empty() {
+            println!("ok    {}", name);
+            continue;
+        }
+
+        if apply_fixes && findings.iter().any(|f| f.issue.fixable()) && fix(&mut map) {
+            let written = serde_json::to_vec(&map)
+                .map_err(|e| e.to_string())
+                .and_then(|json| write_atomically(file, &json).map_err(|e| e.to_string()));
+            match written {
+                Ok(()) => {
+                    println!("FIXED {}", name);
+                    print_findings(&findings);
+                    fixed += 1;
+                    let remaining = check(&map);
+                    if !remaining.is_empty() {
+                        println!("  still failing:");
+                        print_findings(&remaining);
+                        failed += 1;
+                    }
+                }
+                Err(e) => {
+                    println!("FAIL  {}\n    could not write fixes: {}", name, e);
+                    print_findings(&findings);
+                    failed += 1;
+                }
+            }
+            continue;
+        }
+
+        println!("FAIL  {}", name);
+        print_findings(&findings);
+        failed += 1;
+    }
+
+    println!(
+        "\n{} maps checked, {} failed, {} fixed",
+        files.len(),
+        failed,
+        fixed
+    );
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
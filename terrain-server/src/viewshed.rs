@@ -0,0 +1,316 @@
+//! What an observer can see of the terrain around them.
+//!
+//! `POST /api/maps/{name}/viewshed` sweeps rays over the mosaic from the
+//! observer's eye to every node on the border of the square around them,
+//! keeping the steepest elevation angle seen so far along each ray; a node
+//! is visible when some ray reaches it at or above that angle. This is the
+//! usual "R2" approximation, exact enough at the mosaic's resolution and
+//! linear in the number of nodes covered. Results are mapped back onto each
+//! chunk's own samples.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{AppState, api::ApiError, mosaic::Mosaic};
+
+/// Upper bound on the mosaic nodes one viewshed may cover.
+const MAX_VIEWSHED_CELLS: usize = 16 * 1024 * 1024;
+
+/// Mask value of samples outside the radius or without terrain.
+const OUT_OF_RANGE: u8 = 0;
+const HIDDEN: u8 = 1;
+const VISIBLE: u8 = 2;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewshedRequest {
+    /// `[x, z]` of the observer, who stands on the terrain.
+    observer: [f32; 2],
+    /// Eye height above the ground.
+    #[serde(default = "default_eye_height")]
+    eye_height: f32,
+    /// Height above the ground that has to be in sight for a sample to count
+    /// as visible, e.g. a player's head; the ground itself by default.
+    #[serde(default)]
+    target_height: f32,
+    /// How far the observer can see, horizontally.
+    radius: f32,
+}
+
+fn default_eye_height() -> f32 {
+    1.7
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkViewshed {
+    name: String,
+    resolution: u32,
+    /// Samples in range that are visible.
+    visible: usize,
+    /// Samples in range.
+    in_range: usize,
+    /// One value per heightmap sample, in heightmap order: 0 out of range,
+    /// 1 hidden, 2 visible.
+    mask: Vec<u8>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewshedResponse {
+    scene: String,
+    /// `[x, y, z]` of the observer's eye.
+    eye: [f32; 3],
+    radius: f32,
+    /// Share of the samples in range that are visible, from 0 to 1.
+    coverage: f32,
+    /// Chunks with at least one sample in range.
+    chunks: Vec<ChunkViewshed>,
+}
+
+/// Visibility of the mosaic nodes in the square around the eye.
+struct Sweep {
+    col0: usize,
+    row0: usize,
+    cols: usize,
+    visible: Vec<bool>,
+}
+
+impl Sweep {
+    fn is_visible(&self, col: usize, row: usize) -> bool {
+        col >= self.col0
+            && row >= self.row0
+            && col - self.col0 < self.cols
+            && self
+                .visible
+                .get((row - self.row0) * self.cols + col - self.col0)
+                .copied()
+                .unwrap_or(false)
+    }
+}
+
+fn sweep(
+    mosaic: &Mosaic,
+    eye: [f32; 3],
+    target_height: f32,
+    radius: f32,
+) -> Result<Sweep, ApiError> {
+    let cell = mosaic.cell_size;
+    let [ex, ey, ez] = eye;
+    let (eu, ev) = ((ex - mosaic.min_x) / cell, (ez - mosaic.min_z) / cell);
+    let reach = radius / cell;
+
+    let clamp_col = |u: f32| u.clamp(0.0, (mosaic.cols - 1) as f32) as usize;
+    let clamp_row = |v: f32| v.clamp(0.0, (mosaic.rows - 1) as f32) as usize;
+    let (col0, col1) = (
+        clamp_col((eu - reach).floor()),
+        clamp_col((eu + reach).ceil()),
+    );
+    let (row0, row1) = (
+        clamp_row((ev - reach).floor()),
+        clamp_row((ev + reach).ceil()),
+    );
+    let (cols, rows) = (col1 - col0 + 1, row1 - row0 + 1);
+    if cols.saturating_mul(rows) > MAX_VIEWSHED_CELLS {
+        return Err(ApiError::BadRequest(format!(
+            "viewshed would cover {}×{} samples, more than the limit of {}; use a smaller radius",
+            cols, rows, MAX_VIEWSHED_CELLS
+        )));
+    }
+
+    let mut visible = vec![false; cols * rows];
+    let mut border = Vec::with_capacity(2 * (cols + rows));
+    for col in col0..=col1 {
+        border.push((col, row0));
+        border.push((col, row1));
+    }
+    for row in row0..=row1 {
+        border.push((col0, row));
+        border.push((col1, row));
+    }
+
+    for (end_col, end_row) in border {
+        let (du, dv) = (end_col as f32 - eu, end_row as f32 - ev);
+        // One step per cell along the longer axis
+        let steps = du.abs().max(dv.abs()).ceil() as usize;
+        let mut steepest = f32::NEG_INFINITY;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let (u, v) = (eu + du * t, ev + dv * t);
+            let distance = (du * t).hypot(dv * t) * cell;
+            if distance > radius {
+                break;
+            }
+            let (x, z) = (mosaic.min_x + u * cell, mosaic.min_z + v * cell);
+            let Some(ground) = mosaic.sample(x, z) else {
+                continue;
+            };
+            let (col, row) = (u.round() as usize, v.round() as usize);
+            if (ground + target_height - ey) / distance >= steepest
+                && (col0..=col1).contains(&col)
+                && (row0..=row1).contains(&row)
+            {
+                visible[(row - row0) * cols + col - col0] = true;
+            }
+            steepest = steepest.max((ground - ey) / distance);
+        }
+    }
+    // The ground under the observer's feet
+    let (col, row) = (eu.round() as usize, ev.round() as usize);
+    if (col0..=col1).contains(&col) && (row0..=row1).contains(&row) {
+        visible[(row - row0) * cols + col - col0] = true;
+    }
+
+    Ok(Sweep {
+        col0,
+        row0,
+        cols,
+        visible,
+    })
+}
+
+pub async fn get_viewshed(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<ViewshedRequest>,
+) -> Result<Json<ViewshedResponse>, ApiError> {
+    if !req.observer.iter().all(|c| c.is_finite()) {
+        return Err(ApiError::BadRequest(
+            "observer must be two finite numbers".to_string(),
+        ));
+    }
+    for (field, value) in [
+        ("eyeHeight", req.eye_height),
+        ("targetHeight", req.target_height),
+    ] {
+        if !(value.is_finite() && value >= 0.0) {
+            return Err(ApiError::BadRequest(format!(
+                "{} must be a non-negative number",
+                field
+            )));
+        }
+    }
+    if !(req.radius.is_finite() && req.radius > 0.0) {
+        return Err(ApiError::BadRequest(
+            "radius must be a positive number".to_string(),
+        ));
+    }
+
+    let map = state.map(&name).await?;
+    tokio::task::spawn_blocking(move || {
+        let mosaic = map.mosaic()?;
+        let [ox, oz] = req.observer;
+        let ground = mosaic
+            .sample(ox, oz)
+            .ok_or_else(|| ApiError::BadRequest("observer is not on the terrain".to_string()))?;
+        let eye = [ox, ground + req.eye_height, oz];
+        let sweep = sweep(mosaic, eye, req.target_height, req.radius)?;
+
+        let indices = map.index.intersecting(
+            [ox - req.radius, oz - req.radius],
+            [ox + req.radius, oz + req.radius],
+        );
+        let (mut visible_total, mut in_range_total) = (0, 0);
+        let mut chunks = Vec::new();
+        for index in indices {
+            let chunk = &map.data.terrains[index];
+            let res = chunk.resolution as usize;
+            let last = (res - 1) as f32;
+            let (mut visible, mut in_range) = (0, 0);
+            let mut mask = vec![OUT_OF_RANGE; res * res];
+            for row in 0..res {
+                let z = chunk.z + row as f32 / last * chunk.depth;
+                for col in 0..res {
+                    let x = chunk.x + col as f32 / last * chunk.width;
+                    if (x - ox).hypot(z - oz) > req.radius {
+                        continue;
+                    }
+                    let node_col = ((x - mosaic.min_x) / mosaic.cell_size).round() as usize;
+                    let node_row = ((z - mosaic.min_z) / mosaic.cell_size).round() as usize;
+                    in_range += 1;
+                    mask[row * res + col] = if sweep.is_visible(node_col, node_row) {
+                        visible += 1;
+                        VISIBLE
+                    } else {
+                        HIDDEN
+                    };
+                }
+            }
+            if in_range == 0 {
+                continue;
+            }
+            visible_total += visible;
+            in_range_total += in_range;
+            chunks.push(ChunkViewshed {
+                name: chunk.name.clone(),
+                resolution: chunk.resolution,
+                visible,
+                in_range,
+                mask,
+            });
+        }
+
+        Ok(Json(ViewshedResponse {
+            scene: map.data.scene.clone(),
+            eye,
+            radius: req.radius,
+            coverage: if in_range_total > 0 {
+                visible_total as f32 / in_range_total as f32
+            } else {
+                0.0
+            },
+            chunks,
+        }))
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Viewshed task failed".to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{MapFile, TerrainChunk};
+
+    /// 9×9 mosaic, flat but for a ridge of height `ridge` along x = 4.
+    fn ridged(ridge: f32) -> Mosaic {
+        let heights = (0..81)
+            .map(|i| if i % 9 == 4 { ridge } else { 0.0 })
+            .collect();
+        Mosaic::new(&MapFile {
+            scene: "test".to_string(),
+            terrains: vec![TerrainChunk::from_heights("a", 0.0, 0.0, heights)],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn a_ridge_hides_the_ground_behind_it() {
+        let sweep = sweep(&ridged(5.0), [1.0, 1.7, 4.0], 0.0, 20.0).unwrap();
+        for row in 0..9 {
+            for col in 0..=4 {
+                assert!(sweep.is_visible(col, row), "({}, {})", col, row);
+            }
+            for col in 5..9 {
+                assert!(!sweep.is_visible(col, row), "({}, {})", col, row);
+            }
+        }
+    }
+
+    #[test]
+    fn targets_show_where_they_reach_above_the_ridge_line() {
+        // The line over the ridge climbs 1.1 per unit: 6.1 at x = 5, 8.8 at x = 8
+        let sweep = sweep(&ridged(5.0), [1.0, 1.7, 4.0], 7.0, 20.0).unwrap();
+        assert!(sweep.is_visible(5, 4));
+        assert!(!sweep.is_visible(8, 4));
+    }
+
+    #[test]
+    fn flat_ground_is_all_visible_within_the_radius() {
+        let sweep = sweep(&ridged(0.0), [4.0, 1.7, 4.0], 0.0, 20.0).unwrap();
+        assert!((0..81).all(|i| sweep.is_visible(i % 9, i / 9)));
+    }
+}
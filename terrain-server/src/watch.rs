@@ -0,0 +1,138 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher, event::ModifyKind};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{sync::mpsc, time::Instant};
+
+use crate::{
+    AppState, PlayerUpdate,
+    map::{LoadedMap, MapChange, MapError},
+    meta,
+};
+
+/// Waits this long after the last write to a file before re-parsing it, so a
+/// map that is still being written by the exporter is only loaded once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches `maps_dir` and keeps `AppState::maps` in sync with it, announcing
+/// every change over the WebSocket. The returned watcher must be kept alive.
+pub fn spawn(state: Arc<AppState>) -> notify::Result<RecommendedWatcher> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        let event = match result {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Error watching maps directory: {}", e);
+                return;
+            }
+        };
+        // Reads (including our own) and metadata changes don't alter a map
+        let relevant = match event.kind {
+            EventKind::Create(_) | EventKind::Remove(_) => true,
+            EventKind::Modify(ModifyKind::Metadata(_)) => false,
+            EventKind::Modify(_) => true,
+            _ => false,
+        };
+        if relevant {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+    })?;
+    watcher.watch(&state.maps_dir, RecursiveMode::NonRecursive)?;
+
+    tokio::spawn(async move {
+        // Last event time per changed file
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        loop {
+            let next_due = pending.values().min().map(|&t| t + DEBOUNCE);
+            let received = match next_due {
+                Some(due) => tokio::time::timeout_at(due, rx.recv()).await.ok(),
+                None => Some(rx.recv().await),
+            };
+            match received {
+                Some(Some(path)) => {
+                    // A changed sidecar reloads the map it belongs to
+                    let map_path = if path.extension().is_some_and(|ext| ext == "json") {
+                        Some(path)
+                    } else {
+                        meta::map_path(&path)
+                    };
+                    if let Some(map_path) = map_path {
+                        pending.insert(map_path, Instant::now());
+                    }
+                }
+                // Watcher dropped
+                Some(None) => break,
+                None => {
+                    let now = Instant::now();
+                    let settled: Vec<PathBuf> = pending
+                        .iter()
+                        .filter(|&(_, &t)| now >= t + DEBOUNCE)
+                        .map(|(path, _)| path.clone())
+                        .collect();
+                    for path in settled {
+                        pending.remove(&path);
+                        reload(&state, path).await;
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+async fn reload(state: &AppState, path: PathBuf) {
+    let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+        return;
+    };
+    let file_name = file_name.to_string();
+
+    // Parse outside the lock; large maps take a moment
+    let loaded = if path.is_file() {
+        let name = file_name.clone();
+        match tokio::task::spawn_blocking(move || LoadedMap::load(&path, name)).await {
+            Ok(result) => Some(result),
+            Err(_) => return,
+        }
+    } else {
+        None
+    };
+
+    // Maps written through the API are already loaded by the time the
+    // watcher sees them
+    if let Some(Ok(map)) = &loaded
+        && let Some(current) = state.maps.read().await.get(&file_name)
+        && current.assets.content_hash() == map.assets.content_hash()
+        && current.meta == map.meta
+    {
+        return;
+    }
+    publish(state, file_name, loaded).await;
+}
+
+/// Records a (re)loaded or deleted map in the catalog and announces the change
+/// to connected clients.
+pub async fn publish(
+    state: &AppState,
+    file_name: String,
+    loaded: Option<Result<LoadedMap, MapError>>,
+) {
+    let change = state.maps.write().await.apply(file_name, loaded);
+    let update = match change {
+        Some(MapChange::Added(map)) => {
+            println!("Map {} added", map);
+            PlayerUpdate::MapAdded { map }
+        }
+        Some(MapChange::Updated(map)) => {
+            println!("Map {} updated", map);
+            PlayerUpdate::MapUpdated { map }
+        }
+        Some(MapChange::Removed(map)) => {
+            println!("Map {} removed", map);
+            PlayerUpdate::MapRemoved { map }
+        }
+        None => return,
+    };
+    let _ = state.tx.send(update);
+}
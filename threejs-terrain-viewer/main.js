@@ -36,14 +36,29 @@ scene.add(gridHelper);
 // --- State ---
 let currentMeshes = [];
 let isWireframe = false;
+let showContours = false;
+let showNavMesh = false;
+let navMeshOverlay = null; // Walkable navmesh of the current map, if shown
+let contourLines = null; // Contour overlay of the current map, if shown
+let waterMesh = null; // Water surface from the map's metadata, if it has one
+let pathStart = null; // First shift-clicked point of a path preview
+let hasViewshed = false; // Whether the terrain is tinted by a viewshed
+let pathLine = null; // Previewed walking path, if any
+let currentMapInfo = null; // Server-computed stats of the loaded map
 let currentTerrainData = null; // Store current terrain data for height calculation
+let currentMapFile = null; // File name of the loaded map, sent with new players
 let playerMeshes = new Map(); // Map of player ID to mesh
 let mouseWorldPos = { x: 0, z: 0, valid: false }; // Mouse position in world coordinates
 
 // --- UI Elements ---
 const mapSelect = document.getElementById('map-select');
+const mapGallery = document.getElementById('map-gallery');
+const mapSearch = document.getElementById('map-search');
+const mapSort = document.getElementById('map-sort');
 const infoDiv = document.getElementById('info-content');
 const toggleWireframeBtn = document.getElementById('toggle-wireframe');
+const toggleContoursBtn = document.getElementById('toggle-contours');
+const toggleNavMeshBtn = document.getElementById('toggle-navmesh');
 const clearPlayersBtn = document.getElementById('clear-players');
 
 // WebSocket connection
@@ -53,11 +68,14 @@ let ws = null;
 
 async function fetchMapList() {
     try {
-        // Try fetching from the Rust backend API
-        const res = await fetch('/api/maps');
+        // Try fetching from the Rust backend API, filtered and sorted server-side
+        const params = new URLSearchParams({ sort: mapSort.value });
+        const query = mapSearch.value.trim();
+        if (query) params.set('q', query);
+        const res = await fetch(`/api/maps?${params}`);
         if (!res.ok) throw new Error('Failed to fetch map list');
-        const maps = await res.json();
-        return maps;
+        const list = await res.json();
+        return list.maps;
     } catch (e) {
         console.error('Error fetching map list:', e);
         // Fallback for dev mode without backend proxy
@@ -72,6 +90,206 @@ function clearScene() {
         if (mesh.material) mesh.material.dispose();
     });
     currentMeshes = [];
+    hasViewshed = false;
+    removeContours();
+    removeNavMesh();
+    removePath();
+    pathStart = null;
+    if (waterMesh) {
+        scene.remove(waterMesh);
+        waterMesh.geometry.dispose();
+        waterMesh.material.dispose();
+        waterMesh = null;
+    }
+}
+
+function addWaterPlane(bounds, level) {
+    const width = bounds.max[0] - bounds.min[0];
+    const depth = bounds.max[2] - bounds.min[2];
+    const geometry = new THREE.PlaneGeometry(width, depth);
+    geometry.rotateX(-Math.PI / 2);
+    const material = new THREE.MeshStandardMaterial({
+        color: 0x2a6fdb,
+        transparent: true,
+        opacity: 0.45,
+        side: THREE.DoubleSide
+    });
+    waterMesh = new THREE.Mesh(geometry, material);
+    waterMesh.position.set(bounds.min[0] + width / 2, level, bounds.min[2] + depth / 2);
+    scene.add(waterMesh);
+}
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+function removeContours() {
+    if (!contourLines) return;
+    scene.remove(contourLines);
+    contourLines.geometry.dispose();
+    contourLines.material.dispose();
+    contourLines = null;
+}
+
+// Roughly 20 contour levels, at a round 1/2/5 step
+function contourInterval(info) {
+    const raw = Math.max(info.maxHeight - info.minHeight, 1e-3) / 20;
+    const magnitude = Math.pow(10, Math.floor(Math.log10(raw)));
+    const step = [1, 2, 5, 10].find(s => s * magnitude >= raw);
+    return step * magnitude;
+}
+
+async function loadContours(mapFile, info) {
+    removeContours();
+    try {
+        const interval = contourInterval(info);
+        const res = await fetch(`/api/maps/${mapFile}/contours?interval=${interval}`);
+        if (!res.ok) throw new Error(`Failed to fetch contours for ${mapFile}`);
+        const geojson = await res.json();
+        // The map may have changed while we were waiting
+        if (mapFile !== currentMapFile) return;
+
+        // Line strings become pairs of points, lifted slightly above the surface
+        const positions = [];
+        geojson.features.forEach(feature => {
+            const y = feature.properties.elevation + 0.05;
+            const points = feature.geometry.coordinates;
+            for (let i = 1; i < points.length; i++) {
+                positions.push(points[i - 1][0], y, points[i - 1][1]);
+                positions.push(points[i][0], y, points[i][1]);
+            }
+        });
+
+        const geometry = new THREE.BufferGeometry();
+        geometry.setAttribute('position', new THREE.Float32BufferAttribute(positions, 3));
+        const material = new THREE.LineBasicMaterial({ color: 0xffffff, transparent: true, opacity: 0.6 });
+        contourLines = new THREE.LineSegments(geometry, material);
+        scene.add(contourLines);
+    } catch (e) {
+        console.error('Error loading contours:', e);
+    }
+}
+
+function removeNavMesh() {
+    if (!navMeshOverlay) return;
+    scene.remove(navMeshOverlay);
+    navMeshOverlay.geometry.dispose();
+    navMeshOverlay.material.dispose();
+    navMeshOverlay = null;
+}
+
+async function loadNavMesh(mapFile) {
+    removeNavMesh();
+    try {
+        const res = await fetch(`/api/maps/${mapFile}/navmesh`);
+        if (!res.ok) throw new Error(`Failed to fetch navmesh for ${mapFile}`);
+        const navMesh = await res.json();
+        if (mapFile !== currentMapFile) return;
+
+        const geometry = new THREE.BufferGeometry();
+        geometry.setAttribute('position', new THREE.Float32BufferAttribute(navMesh.positions, 3));
+        geometry.setIndex(navMesh.indices);
+        // Lifted slightly so it doesn't z-fight with the terrain
+        geometry.translate(0, 0.1, 0);
+        const material = new THREE.MeshBasicMaterial({
+            color: 0x2ec4b6,
+            transparent: true,
+            opacity: 0.35,
+            side: THREE.DoubleSide,
+            depthWrite: false
+        });
+        navMeshOverlay = new THREE.Mesh(geometry, material);
+        scene.add(navMeshOverlay);
+        console.log(`Navmesh: ${navMesh.polygons} polygons, ${(navMesh.coverage * 100).toFixed(1)}% walkable`);
+    } catch (e) {
+        console.error('Error loading navmesh:', e);
+    }
+}
+
+// Vertex color multipliers for viewshed mask values: out of range, hidden, visible
+const VIEWSHED_TINTS = [[1, 1, 1], [0.45, 0.45, 0.5], [2.4, 2.0, 0.35]];
+
+function clearViewshed() {
+    if (!hasViewshed) return;
+    currentMeshes.forEach(mesh => {
+        const color = mesh.geometry.attributes.color;
+        color.array.fill(1);
+        color.needsUpdate = true;
+    });
+    hasViewshed = false;
+}
+
+async function showViewshed(x, z) {
+    try {
+        const mapFile = currentMapFile;
+        const bounds = currentMapInfo?.bounds;
+        // A quarter of the map's longer side
+        const radius = bounds
+            ? Math.max(bounds.max[0] - bounds.min[0], bounds.max[2] - bounds.min[2]) / 4
+            : 100;
+        const res = await fetch(`/api/maps/${mapFile}/viewshed`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ observer: [x, z], radius })
+        });
+        if (!res.ok) throw new Error(await res.text());
+        const viewshed = await res.json();
+        if (mapFile !== currentMapFile) return;
+
+        clearViewshed();
+        const masks = new Map(viewshed.chunks.map(c => [c.name, c.mask]));
+        currentMeshes.forEach(mesh => {
+            const mask = masks.get(mesh.userData.chunkName);
+            if (!mask) return;
+            const color = mesh.geometry.attributes.color;
+            mask.forEach((value, i) => color.setXYZ(i, ...VIEWSHED_TINTS[value]));
+            color.needsUpdate = true;
+        });
+        hasViewshed = true;
+        console.log(`Viewshed within ${radius.toFixed(0)} units: ${(viewshed.coverage * 100).toFixed(1)}% visible`);
+    } catch (e) {
+        console.error('Error computing viewshed:', e);
+    }
+}
+
+function removePath() {
+    if (!pathLine) return;
+    scene.remove(pathLine);
+    pathLine.geometry.dispose();
+    pathLine.material.dispose();
+    pathLine = null;
+}
+
+async function previewPath(start, end) {
+    removePath();
+    try {
+        const mapFile = currentMapFile;
+        const res = await fetch(`/api/maps/${mapFile}/path`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            // Paths follow the navmesh while it is shown
+            body: JSON.stringify({ start, end, mode: showNavMesh ? 'navmesh' : 'grid' })
+        });
+        if (!res.ok) throw new Error(await res.text());
+        const path = await res.json();
+        if (mapFile !== currentMapFile) return;
+        if (!path.found) {
+            console.log('No walkable path between the points');
+            return;
+        }
+
+        const positions = path.waypoints.flatMap(([x, y, z]) => [x, y + 0.5, z]);
+        const geometry = new THREE.BufferGeometry();
+        geometry.setAttribute('position', new THREE.Float32BufferAttribute(positions, 3));
+        const material = new THREE.LineBasicMaterial({ color: 0xffd166 });
+        pathLine = new THREE.Line(geometry, material);
+        scene.add(pathLine);
+        console.log(`Path of ${path.length.toFixed(1)} units through ${path.waypoints.length} waypoints`);
+    } catch (e) {
+        console.error('Error finding path:', e);
+    }
 }
 
 function getHeightAtPosition(x, z, terrainData) {
@@ -106,6 +324,89 @@ function getHeightAtPosition(x, z, terrainData) {
     return 0; // Default height if not on any terrain
 }
 
+// Decodes the server's compact binary map format (see terrain-server/src/binary.rs)
+function decodeBinaryMap(buffer) {
+    const view = new DataView(buffer);
+    const decoder = new TextDecoder();
+    let offset = 0;
+
+    const readString = () => {
+        const len = view.getUint16(offset, true);
+        offset += 2;
+        const str = decoder.decode(new Uint8Array(buffer, offset, len));
+        offset += len;
+        return str;
+    };
+    const readF32 = () => {
+        const v = view.getFloat32(offset, true);
+        offset += 4;
+        return v;
+    };
+
+    const magic = decoder.decode(new Uint8Array(buffer, 0, 4));
+    if (magic !== 'U2TM') throw new Error('Not a binary terrain map');
+    offset = 6; // magic + version
+
+    const scene = readString();
+    const chunkCount = view.getUint32(offset, true);
+    offset += 4;
+
+    const terrains = [];
+    for (let c = 0; c < chunkCount; c++) {
+        const name = readString();
+        const x = readF32(), y = readF32(), z = readF32();
+        const width = readF32(), depth = readF32(), maxHeight = readF32();
+        const resolution = view.getUint32(offset, true);
+        offset += 4;
+        const encoding = view.getUint8(offset);
+        offset += 1;
+
+        const count = resolution * resolution;
+        const heightMap = new Float32Array(count);
+        for (let i = 0; i < count; i++) {
+            if (encoding === 0) {
+                // Math.fround mirrors the exporter's single-precision math
+                const q = view.getInt16(offset, true);
+                heightMap[i] = Math.fround(Math.fround(q / 32768) * maxHeight);
+                offset += 2;
+            } else {
+                heightMap[i] = view.getFloat32(offset, true);
+                offset += 4;
+            }
+        }
+
+        terrains.push({ name, x, y, z, width, depth, maxHeight, resolution, heightMap });
+    }
+
+    return { scene, terrains };
+}
+
+async function fetchMapInfo(mapFile) {
+    try {
+        const res = await fetch(`/api/maps/${mapFile}/info`);
+        if (!res.ok) throw new Error(`Failed to fetch info for ${mapFile}`);
+        return await res.json();
+    } catch (e) {
+        console.error('Error fetching map info:', e);
+        return null;
+    }
+}
+
+function fitCameraToBounds(bounds) {
+    const center = new THREE.Vector3(
+        (bounds.min[0] + bounds.max[0]) / 2,
+        (bounds.min[1] + bounds.max[1]) / 2,
+        (bounds.min[2] + bounds.max[2]) / 2
+    );
+    const extent = Math.max(
+        bounds.max[0] - bounds.min[0],
+        bounds.max[2] - bounds.min[2]
+    );
+
+    controls.target.copy(center);
+    camera.position.set(center.x, center.y + extent * 0.75, center.z + extent * 0.75);
+}
+
 async function loadMap(mapFile) {
     clearScene();
     infoDiv.innerHTML = '<p class="placeholder">Loading terrain...</p>';
@@ -116,10 +417,15 @@ async function loadMap(mapFile) {
         // However, 'public/maps' are NOT in 'dist' unless copied by build process.
         // Vite copies public/* to dist root. So public/maps/x.json -> dist/maps/x.json
         // So fetching /maps/x.json should work if the server serves dist correctly.
-        const res = await fetch(`/maps/${mapFile}`);
+        const res = await fetch(`/maps/${mapFile}`, {
+            headers: { 'Accept': 'application/octet-stream, application/json;q=0.9' }
+        });
         if (!res.ok) throw new Error(`Failed to load ${mapFile}`);
 
-        const data = await res.json();
+        // Fall back to JSON when served without the backend (e.g. Vite dev server)
+        const data = res.headers.get('Content-Type')?.includes('application/octet-stream')
+            ? decodeBinaryMap(await res.arrayBuffer())
+            : await res.json();
 
         if (!data.terrains || data.terrains.length === 0) {
             throw new Error('Map contains no terrain data');
@@ -127,6 +433,7 @@ async function loadMap(mapFile) {
 
         // Store terrain data for height calculations
         currentTerrainData = data;
+        currentMapFile = mapFile;
 
         let totalVerts = 0;
 
@@ -149,15 +456,21 @@ async function loadMap(mapFile) {
             geometry.rotateX(-Math.PI / 2);
             geometry.computeVertexNormals();
 
+            // White vertex colors keep the material color; viewsheds tint them
+            const colors = new Float32Array(posAttr.count * 3).fill(1);
+            geometry.setAttribute('color', new THREE.BufferAttribute(colors, 3));
+
             // Material
             const material = new THREE.MeshStandardMaterial({
                 color: 0x646cff,
                 wireframe: isWireframe,
                 side: THREE.DoubleSide,
-                flatShading: true
+                flatShading: true,
+                vertexColors: true
             });
 
             const mesh = new THREE.Mesh(geometry, material);
+            mesh.userData.chunkName = chunk.name;
 
             // Position (Unity corner to Three.js center)
             mesh.position.set(
@@ -171,11 +484,23 @@ async function loadMap(mapFile) {
             totalVerts += posAttr.count;
         });
 
-        // Center camera on first chunk if needed
-        if (data.terrains.length > 0) {
+        // Fit the camera to the whole map using the server-computed bounds
+        const info = await fetchMapInfo(mapFile);
+        currentMapInfo = info;
+        const meta = info?.meta;
+        if (info) {
+            // Designers can pin a camera pose in the map's .meta.toml
+            if (meta?.camera) {
+                controls.target.fromArray(meta.camera.target);
+                camera.position.fromArray(meta.camera.position);
+            } else {
+                fitCameraToBounds(info.bounds);
+            }
+            if (meta?.waterLevel != null) addWaterPlane(info.bounds, meta.waterLevel);
+            if (showContours) loadContours(mapFile, info);
+            if (showNavMesh) loadNavMesh(mapFile);
+        } else {
             const first = data.terrains[0];
-            // Don't reset camera completely, just look at it? 
-            // Actually, let's keep user control, but maybe look at the center of the first chunk
             controls.target.set(
                 first.x + first.width / 2,
                 first.y,
@@ -184,6 +509,10 @@ async function loadMap(mapFile) {
         }
 
         infoDiv.innerHTML = `
+            ${meta?.displayName ? `<p><strong>${escapeHtml(meta.displayName)}</strong></p>` : ''}
+            ${meta?.description ? `<p>${escapeHtml(meta.description)}</p>` : ''}
+            ${meta?.tags?.length ? `<p><strong>Tags:</strong> ${meta.tags.map(escapeHtml).join(', ')}</p>` : ''}
+            ${meta?.spawnPoints?.length ? `<p><strong>Spawn Points:</strong> ${meta.spawnPoints.length}</p>` : ''}
             <p><strong>Chunks:</strong> ${data.terrains.length}</p>
             <p><strong>Total Vertices:</strong> ${totalVerts.toLocaleString()}</p>
         `;
@@ -203,6 +532,24 @@ toggleWireframeBtn.addEventListener('click', () => {
     });
 });
 
+toggleContoursBtn.addEventListener('click', () => {
+    showContours = !showContours;
+    if (showContours && currentMapFile && currentMapInfo) {
+        loadContours(currentMapFile, currentMapInfo);
+    } else {
+        removeContours();
+    }
+});
+
+toggleNavMeshBtn.addEventListener('click', () => {
+    showNavMesh = !showNavMesh;
+    if (showNavMesh && currentMapFile) {
+        loadNavMesh(currentMapFile);
+    } else {
+        removeNavMesh();
+    }
+});
+
 clearPlayersBtn.addEventListener('click', async () => {
     await clearAllPlayers();
     console.log('All players cleared');
@@ -212,6 +559,11 @@ mapSelect.addEventListener('change', (e) => {
     if (e.target.value) {
         loadMap(e.target.value);
     }
+    highlightGalleryItem(e.target.value);
+});
+
+window.addEventListener('keydown', (event) => {
+    if (event.key === 'Escape') clearViewshed();
 });
 
 window.addEventListener('resize', () => {
@@ -258,8 +610,8 @@ function createPlayerMesh(player) {
     });
     const mesh = new THREE.Mesh(geometry, material);
 
-    // Calculate height from terrain
-    const y = getHeightAtPosition(player.x, player.z, currentTerrainData);
+    // Prefer the server-sampled height so every client agrees on elevation
+    const y = player.y ?? getHeightAtPosition(player.x, player.z, currentTerrainData);
     mesh.position.set(player.x, y + 2.5, player.z); // +2.5 to center cylinder (height/2)
 
     scene.add(mesh);
@@ -339,7 +691,7 @@ function handlePlayerUpdate(update) {
         case 'player_moved':
             if (playerMeshes.has(update.id)) {
                 const mesh = playerMeshes.get(update.id);
-                const y = getHeightAtPosition(update.x, update.z, currentTerrainData);
+                const y = update.y ?? getHeightAtPosition(update.x, update.z, currentTerrainData);
                 mesh.position.set(update.x, y + 2.5, update.z);
             }
             break;
@@ -355,6 +707,24 @@ function handlePlayerUpdate(update) {
             }
             break;
 
+        case 'map_added':
+        case 'map_removed':
+            refreshMapList();
+            if (update.type === 'map_removed' && update.map === currentMapFile) {
+                infoDiv.innerHTML = `<p style="color: #ff6b6b">${update.map} was removed from the server</p>`;
+            }
+            break;
+
+        case 'map_updated':
+            // Re-exported on the server; refresh its thumbnail and reload it if
+            // it is the one on screen
+            refreshMapList();
+            if (update.map === currentMapFile) {
+                console.log(`Map ${update.map} updated, reloading`);
+                loadMap(update.map);
+            }
+            break;
+
         case 'all_cleared':
             for (const [id, mesh] of playerMeshes.entries()) {
                 scene.remove(mesh);
@@ -377,7 +747,7 @@ async function createPlayer(x, z) {
         const res = await fetch('/api/players', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({ x, z })
+            body: JSON.stringify({ x, z, map: currentMapFile })
         });
         if (!res.ok) throw new Error('Failed to create player');
         await updatePlayers();
@@ -415,6 +785,22 @@ canvas.addEventListener('click', async (event) => {
 
     if (intersects.length > 0) {
         const point = intersects[0].point;
+        // Alt-click shows what can be seen from that point
+        if (event.altKey) {
+            showViewshed(point.x, point.z);
+            return;
+        }
+        // Shift-click picks the start, then the end of a path preview
+        if (event.shiftKey) {
+            if (pathStart) {
+                previewPath(pathStart, [point.x, point.z]);
+                pathStart = null;
+            } else {
+                removePath();
+                pathStart = [point.x, point.z];
+            }
+            return;
+        }
         await createPlayer(point.x, point.z);
         console.log(`Created player at (${point.x.toFixed(2)}, ${point.z.toFixed(2)})`);
     }
@@ -427,25 +813,63 @@ window.updatePlayers = updatePlayers;
 
 // --- Init ---
 
-async function init() {
+function highlightGalleryItem(mapFile) {
+    mapGallery.querySelectorAll('img').forEach(img => {
+        img.classList.toggle('selected', img.dataset.file === mapFile);
+    });
+}
+
+let searchTimer = null;
+mapSearch.addEventListener('input', () => {
+    clearTimeout(searchTimer);
+    searchTimer = setTimeout(refreshMapList, 200);
+});
+mapSort.addEventListener('change', refreshMapList);
+
+async function refreshMapList() {
     const maps = await fetchMapList();
 
     mapSelect.innerHTML = '<option value="" disabled selected>Select a terrain...</option>';
+    mapGallery.innerHTML = '';
 
     if (maps.length === 0) {
         const opt = document.createElement('option');
-        opt.text = "No maps found";
+        opt.text = mapSearch.value.trim() ? "No matching maps" : "No maps found";
         opt.disabled = true;
         mapSelect.appendChild(opt);
     } else {
         maps.forEach(map => {
             const opt = document.createElement('option');
-            opt.value = map;
-            opt.textContent = map;
+            opt.value = map.file;
+            const title = map.displayName ?? map.name;
+            opt.textContent = map.index !== null ? `#${map.index} ${title}` : title;
             mapSelect.appendChild(opt);
+
+            // Server-rendered minimap, clicked to load the map
+            const img = document.createElement('img');
+            img.src = map.thumbnail;
+            img.alt = title;
+            img.title = `${title} (${map.file}, ${map.chunkCount} chunks)`;
+            img.loading = 'lazy';
+            img.dataset.file = map.file;
+            img.addEventListener('click', () => {
+                mapSelect.value = map.file;
+                mapSelect.dispatchEvent(new Event('change'));
+            });
+            mapGallery.appendChild(img);
         });
     }
 
+    // Keep the loaded map selected across refreshes
+    if (currentMapFile && maps.some(map => map.file === currentMapFile)) {
+        mapSelect.value = currentMapFile;
+        highlightGalleryItem(currentMapFile);
+    }
+}
+
+async function init() {
+    await refreshMapList();
+
     animate();
 
     // Connect to WebSocket for real-time updates